@@ -0,0 +1,152 @@
+use serde::Deserialize;
+
+/// How the ANSI escape sequences in the output of the commands are compared to the expected output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnsiMode {
+    /// Compare the escape sequences as is.
+    #[default]
+    Keep,
+    /// Remove the escape sequences.
+    Strip,
+    /// Render the colours and styles into a readable markup, e.g. `[bold red]error[/]`, and remove the other escape
+    /// sequences.
+    Markup,
+}
+
+impl AnsiMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "keep" => Some(Self::Keep),
+            "strip" => Some(Self::Strip),
+            "markup" => Some(Self::Markup),
+            _ => None,
+        }
+    }
+
+    pub fn apply(self, text: &str) -> String {
+        match self {
+            Self::Keep => text.into(),
+            Self::Strip => render(text, |_| None),
+            Self::Markup => render(text, sgr_markup),
+        }
+    }
+}
+
+/// Remove the escape sequences from the text, replacing the SGR sequences (colours and styles) with the result of
+/// `render_sgr` for their parameters.
+fn render(text: &str, render_sgr: impl Fn(&str) -> Option<String>) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        if ch != '\x1b' {
+            result.push(ch);
+            continue;
+        }
+
+        match chars.next() {
+            // CSI: parameter and intermediate bytes, then a final byte
+            Some('[') => {
+                let mut params = String::new();
+                while let Some(&ch) = chars.peek()
+                    && ('\x20'..='\x3f').contains(&ch)
+                {
+                    params.push(ch);
+                    chars.next();
+                }
+                if chars.next() == Some('m')
+                    && let Some(markup) = render_sgr(&params)
+                {
+                    result.push_str(&markup);
+                }
+            },
+            // OSC, e.g. a hyperlink: terminated with BEL or ST (`ESC \`)
+            Some(']') => {
+                while let Some(ch) = chars.next() {
+                    if ch == '\x07' || (ch == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+    result
+}
+
+const COLORS: [&str; 8] = ["black", "red", "green", "yellow", "blue", "magenta", "cyan", "white"];
+
+fn sgr_markup(params: &str) -> Option<String> {
+    let codes = params
+        .split([';', ':'])
+        .map(|code| {
+            if code.is_empty() {
+                Some(0)
+            } else {
+                code.parse::<u8>().ok()
+            }
+        })
+        .collect::<Option<Vec<_>>>()?;
+    if codes.iter().all(|code| *code == 0) {
+        return Some("[/]".into());
+    }
+
+    let mut styles = Vec::new();
+    let mut codes = codes.into_iter();
+    while let Some(code) = codes.next() {
+        let style = match code {
+            0 => "/".into(),
+            1 => "bold".into(),
+            2 => "dim".into(),
+            3 => "italic".into(),
+            4 => "underline".into(),
+            7 => "reverse".into(),
+            9 => "strike".into(),
+            22 => "/bold".into(),
+            23 => "/italic".into(),
+            24 => "/underline".into(),
+            27 => "/reverse".into(),
+            29 => "/strike".into(),
+            30..=37 => COLORS[usize::from(code - 30)].into(),
+            39 => "/fg".into(),
+            40..=47 => format!("on_{}", COLORS[usize::from(code - 40)]),
+            49 => "/bg".into(),
+            90..=97 => format!("bright_{}", COLORS[usize::from(code - 90)]),
+            100..=107 => format!("on_bright_{}", COLORS[usize::from(code - 100)]),
+            38 | 48 => {
+                let color = match codes.next()? {
+                    5 => format!("color({})", codes.next()?),
+                    2 => format!("#{:02x}{:02x}{:02x}", codes.next()?, codes.next()?, codes.next()?),
+                    _ => return None,
+                };
+                if code == 38 { color } else { format!("on_{color}") }
+            },
+            code => format!("sgr({code})"),
+        };
+        styles.push(style);
+    }
+    Some(format!("[{}]", styles.join(" ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AnsiMode;
+
+    #[test]
+    fn apply_ansi_modes() {
+        let text = "\x1b[1;31merror\x1b[0m: \x1b[38;5;208mwarn\x1b[39m \x1b[48;2;0;128;255mbg\x1b[m \
+                    \x1b]8;;https://example.com\x1b\\link\x1b]8;;\x07\x1b[2K done";
+
+        assert_eq!(AnsiMode::Keep.apply(text), text);
+        assert_eq!(AnsiMode::Strip.apply(text), "error: warn bg link done");
+        assert_eq!(
+            AnsiMode::Markup.apply(text),
+            "[bold red]error[/]: [color(208)]warn[/fg] [on_#0080ff]bg[/] link done"
+        );
+        assert_eq!(
+            AnsiMode::Markup.apply("\x1b[4;92;100mok\x1b[24m"),
+            "[underline bright_green on_bright_black]ok[/underline]"
+        );
+    }
+}
@@ -1,20 +1,66 @@
 use std::ffi::OsString;
-use std::ops::{Deref, DerefMut};
+use std::ops::{ControlFlow, Deref, DerefMut};
+use std::panic::{self, AssertUnwindSafe};
 use std::path::{Path, PathBuf};
-use std::{env, fs, io, mem};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{env, fs, io, iter, mem, process};
 
 use assert_cmd::Command;
 use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Parser, Tag, TagEnd};
 
-use super::cmd::{Cmd, CmdResponse};
+use super::cmd::{Cmd, CmdResponse, ListOperator, Redirects, checked_join, split_command_list};
+use super::command_stub::{self, CommandStub};
+use super::condition::Condition;
+use super::config::front_matter;
+use super::directive::{CaseOptions, MatchMode, Scope, parse_directive};
+use super::job::{DEFAULT_READY_TIMEOUT, JobCmd, Jobs};
+use super::lexer::{LexError, Operator, Token, tokenize, tokens_source};
+use super::normalize::{NormalizeContext, Normalizer, default_normalizers};
+use super::pty::{PtyProcess, Read, render_terminal_output};
+use super::report::{CommandReport, Recorder, Status, panic_message};
+use super::stub::{HttpStub, STUB_URL_PLACEHOLDER};
 use crate::error::{self, TestError};
 
+/// Languages of the code blocks with commands unless configured otherwise.
+pub const DEFAULT_LANGUAGES: [&str; 2] = ["sh", "shell"];
+
+/// Prefix of a command line that is executed but hidden from the rendered documentation, e.g. `#$ mkdir src`.
+pub const HIDDEN_COMMAND_PREFIX: &str = "#$";
+
+/// Prefix of a line typed into an interactive command, e.g. `> y`.
+pub const INPUT_PREFIX: &str = ">";
+
+/// Commands run by the tester itself instead of a binary.
+const BUILTIN_COMMANDS: [&str; 9] = ["cd", "ls", "mkdir", "rm", "echo", "cat", "wait", "kill", "stub-log"];
+
+/// Maximum time to wait for each prompt of an interactive command unless a timeout is set.
+pub const DEFAULT_PROMPT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Default)]
 pub struct TestSection {
     pub title: String,
+    /// Directories copied into the test directory before the first test case runs.
+    pub fixtures: Vec<PathBuf>,
+    /// Cases of the `setup` blocks, run before the cases of the section.
+    pub setup: Vec<TestCase>,
     pub cases: Vec<TestCase>,
+    /// Cases of the `teardown` blocks, run after the cases of the section even if one of them fails.
+    pub teardown: Vec<TestCase>,
+    /// Responses of the HTTP stub server of the section, declared by the `stub` blocks.
+    pub stubs: Vec<HttpStub>,
+    /// Executables on the `PATH` of the tested binary, declared by the `command=<name>` blocks.
+    pub command_stubs: Vec<CommandStub>,
 }
 
-#[derive(Debug, Default)]
+impl TestSection {
+    /// Iterate over the setup, regular and teardown cases.
+    pub fn all_cases_mut(&mut self) -> impl Iterator<Item = &mut TestCase> {
+        self.setup.iter_mut().chain(&mut self.cases).chain(&mut self.teardown)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct TestCase {
     pub commands: Vec<String>,
     pub cargo_bin_alias: String,
@@ -22,26 +68,264 @@ pub struct TestCase {
     pub test_dir: Option<PathBuf>,
     pub output: ExpectedOutput,
     pub envs: Vec<(OsString, OsString)>,
+    /// File created in the test directory before the commands run.
+    pub file: Option<InlineFile>,
+    /// Path of the file, relative to the test directory, whose content must match the expected output.
+    pub expected_file: Option<String>,
+    /// Options set by the `<!-- md-cli-test: ... -->` directives.
+    pub options: CaseOptions,
+    /// Normalizers applied to the actual and the expected output after the default ones.
+    pub normalizers: Vec<Arc<dyn Normalizer>>,
+    /// Reason to skip the test case, e.g. an unmet condition.
+    pub skip_reason: Option<String>,
+    /// Prompts and replies of an interactive block, the expected output holds the output after the last reply.
+    pub interaction: Vec<InteractionStep>,
+    /// Background jobs of the section, started by the `command &` lines.
+    pub jobs: Jobs,
+    /// URL of the HTTP stub server of the section, substituted for the `${stub_url}` placeholder.
+    pub stub_url: Option<String>,
+    /// Directory of the stub commands, prepended to the `PATH` of the binaries.
+    pub bin_dir: Option<PathBuf>,
+    /// Environment variables inherited by the binaries if the inherited environment is cleared.
+    pub allowed_envs: Option<Vec<String>>,
+    /// Collects the results of the commands for the report of the section.
+    pub recorder: Recorder,
 }
 
-#[derive(Debug, Default)]
+/// Prompt of an interactive command and the line typed after it.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct InteractionStep {
+    /// Output expected since the previous reply.
+    pub prompt: String,
+    /// Line typed into the terminal, without the `> ` prefix and the line ending.
+    pub input: String,
+    pub source_line: Option<usize>,
+}
+
+/// File declared by an annotated code block, e.g. ```` ```toml file=Project.toml ````.
+#[derive(Debug, Default, Clone)]
+pub struct InlineFile {
+    /// Path relative to the test directory.
+    pub path: String,
+    pub content: String,
+}
+
+/// Info string of a fenced code block, e.g. `sh`, `sh,setup`, `sh,cfg(unix)`, `sh,tty=120x40`, `sh,interactive`,
+/// `http,stub,required`, `text,command=editor,exit=1`, `toml file=Project.toml` or `toml expect-file=Project.toml`.
+#[derive(Debug, Default, PartialEq)]
+struct BlockInfo {
+    lang: String,
+    file: Option<String>,
+    expect_file: Option<String>,
+    hook: Option<SectionHook>,
+    /// Whether the `> ` lines of the expected output are typed into the commands.
+    interactive: bool,
+    /// Whether the block declares an HTTP stub, see [`HttpStub`].
+    stub: bool,
+    /// Whether the stub must be requested.
+    required: bool,
+    /// Name of the stub command declared by the block, see [`CommandStub`].
+    command: Option<String>,
+    /// Exit code of the stub command printing the content of the block.
+    exit_code: Option<i32>,
+    /// Options of the block set by the conditions and `tty` attributes.
+    options: CaseOptions,
+}
+
+/// Role of a code block that runs around the cases of a section instead of being one of them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SectionHook {
+    Setup,
+    Teardown,
+}
+
+impl BlockInfo {
+    /// Parse the info string, an unknown attribute is an error for the blocks handled by the tester, i.e. the blocks
+    /// of the `languages` and the stubs.
+    fn parse(info: &str, languages: &[String]) -> Result<Self, String> {
+        let info = info.trim();
+        let mut attrs = info_attrs(info).into_iter();
+        let mut block_info = Self {
+            lang: attrs.next().unwrap_or_default().into(),
+            file: attr_value(info, "file").map(Into::into),
+            expect_file: attr_value(info, "expect-file").map(Into::into),
+            ..Default::default()
+        };
+
+        let mut unknown_attrs = Vec::new();
+        for attr in attrs {
+            match attr {
+                "setup" => block_info.hook = Some(SectionHook::Setup),
+                "teardown" => block_info.hook = Some(SectionHook::Teardown),
+                "interactive" => block_info.interactive = true,
+                "stub" => block_info.stub = true,
+                "required" => block_info.required = true,
+                "tty" => block_info.options.set("tty", "true")?,
+                _ => {
+                    if let Some(size) = attr.strip_prefix("tty=") {
+                        block_info.options.set("tty", size)?;
+                    } else if let Some(name) = attr.strip_prefix("command=") {
+                        block_info.command = Some(name.into());
+                    } else if let Some(code) = attr.strip_prefix("exit=") {
+                        let code = code.parse().map_err(|_| format!("invalid exit code `{code}`"))?;
+                        block_info.exit_code = Some(code);
+                    } else if let Some(condition) = Condition::parse(attr) {
+                        block_info.options.conditions.push(condition?);
+                    } else {
+                        unknown_attrs.push(attr);
+                    }
+                },
+            }
+        }
+
+        let is_handled = block_info.is_test_case(languages) || block_info.stub || block_info.command.is_some();
+        if let Some(attr) = unknown_attrs.first()
+            && is_handled
+        {
+            return Err(format!("unknown attribute `{attr}` of the `{}` block", block_info.lang));
+        }
+        Ok(block_info)
+    }
+
+    /// Whether the block contains commands, `languages` defaults to [`DEFAULT_LANGUAGES`] if empty.
+    fn is_test_case(&self, languages: &[String]) -> bool {
+        if languages.is_empty() {
+            DEFAULT_LANGUAGES.contains(&self.lang.as_str())
+        } else {
+            languages.contains(&self.lang)
+        }
+    }
+}
+
+/// Split the info string into the attributes separated by whitespaces or commas outside of parentheses, e.g.
+/// `cfg(any(unix, windows))`. The attributes end before `file=` or `expect-file=`, whose value may contain any words.
+fn info_attrs(info: &str) -> Vec<&str> {
+    let mut attrs = Vec::new();
+    let mut depth = 0_usize;
+    let mut start = 0;
+
+    for (idx, ch) in info.char_indices() {
+        match ch {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ch if depth == 0 && (ch.is_whitespace() || ch == ',') => {
+                if start < idx {
+                    attrs.push(&info[start..idx]);
+                }
+                start = idx + ch.len_utf8();
+            },
+            _ => {},
+        }
+    }
+    if start < info.len() {
+        attrs.push(&info[start..]);
+    }
+
+    if let Some(end) = attrs
+        .iter()
+        .position(|attr| attr.starts_with("file=") || attr.starts_with("expect-file="))
+    {
+        attrs.truncate(end);
+    }
+    attrs
+}
+
+/// Find the value of the `key=value` attribute in the info string. The value extends to the end of the info
+/// string, so it may contain spaces, and may be quoted.
+fn attr_value<'a>(info: &'a str, key: &str) -> Option<&'a str> {
+    let pattern = format!("{key}=");
+    let start = info.match_indices(&pattern).find_map(|(idx, _)| {
+        let is_attr_start = info[..idx].ends_with(|ch: char| ch.is_whitespace() || ch == ',');
+        is_attr_start.then_some(idx + pattern.len())
+    })?;
+
+    let value = info[start..].trim();
+    let value = value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value);
+    (!value.is_empty()).then_some(value)
+}
+
+/// Code block of a test case being parsed.
+struct CodeBlock {
+    info: BlockInfo,
+    start_line: usize,
+    text: String,
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct ExpectedOutput {
     pub text: String,
     pub source_path: Option<PathBuf>,
     pub source_line: Option<usize>,
 }
 
+/// Output of a single command, e.g. a stage of a pipeline.
+#[derive(Debug, Default)]
+struct CommandOutput {
+    stdout: String,
+    stderr: String,
+    status: i32,
+    /// Whether the output must be checked against the expected output of the test case.
+    is_checked: bool,
+}
+
 enum Multiline {
-    ToEndString(&'static str, String),
+    UnterminatedString(String),
     WithLinesHasEnd(&'static str, String),
 }
 
+impl Multiline {
+    /// Begin the command of a command line, it breaks with the command if it ends on the line or continues on the
+    /// next lines after a trailing `\` or in an unterminated string.
+    fn begin(line: &str) -> ControlFlow<String, Self> {
+        let mut command = line.to_string();
+        match tokenize(&command) {
+            Ok(_) => ControlFlow::Break(command),
+            Err(LexError::UnterminatedEscape { .. }) if command.ends_with('\\') => {
+                command.pop();
+                ControlFlow::Continue(Self::WithLinesHasEnd("\\", command))
+            },
+            Err(_) => ControlFlow::Continue(Self::UnterminatedString(command)),
+        }
+    }
+
+    /// Append the next line of the command, it breaks with the command if the line is the last one.
+    fn push_line(mut self, mut line: &str) -> ControlFlow<String, Self> {
+        self.push('\n');
+
+        let is_last_line = match &self {
+            Self::UnterminatedString(string) => tokenize(&format!("{string}{line}")).is_ok(),
+            Self::WithLinesHasEnd(end, _) => {
+                if line.ends_with(*end) {
+                    if line.len() > 1 {
+                        line = &line[..line.len() - 1];
+                    } else {
+                        line = "";
+                    }
+                    false
+                } else {
+                    true
+                }
+            },
+        };
+
+        self.push_str(line);
+        if is_last_line {
+            ControlFlow::Break(self.into())
+        } else {
+            ControlFlow::Continue(self)
+        }
+    }
+}
+
 impl Deref for Multiline {
     type Target = String;
 
     fn deref(&self) -> &Self::Target {
         match self {
-            Self::ToEndString(_, string) => string,
+            Self::UnterminatedString(string) => string,
             Self::WithLinesHasEnd(_, string) => string,
         }
     }
@@ -50,7 +334,7 @@ impl Deref for Multiline {
 impl DerefMut for Multiline {
     fn deref_mut(&mut self) -> &mut Self::Target {
         match self {
-            Self::ToEndString(_, string) => string,
+            Self::UnterminatedString(string) => string,
             Self::WithLinesHasEnd(_, string) => string,
         }
     }
@@ -59,7 +343,7 @@ impl DerefMut for Multiline {
 impl From<Multiline> for String {
     fn from(value: Multiline) -> Self {
         match value {
-            Multiline::ToEndString(_, string) => string,
+            Multiline::UnterminatedString(string) => string,
             Multiline::WithLinesHasEnd(_, string) => string,
         }
     }
@@ -72,56 +356,22 @@ impl TestCase {
         let mut multiline_command: Option<Multiline> = None;
 
         // Split into commands and expected output
-        for mut line in source.as_ref().lines() {
-            if let Some(mut command) = multiline_command.take() {
-                command.push('\n');
-
-                let is_last_line = match &command {
-                    Multiline::ToEndString(end, _) => line.contains(*end),
-                    Multiline::WithLinesHasEnd(end, _) => {
-                        if line.ends_with(*end) {
-                            if line.len() > 1 {
-                                line = &line[..line.len() - 1];
-                            } else {
-                                line = "";
-                            }
-                            false
-                        } else {
-                            true
-                        }
-                    },
-                };
-
-                command.push_str(line);
-                if is_last_line {
-                    commands.push(command.into());
-                } else {
-                    multiline_command = Some(command);
+        for line in source.as_ref().lines() {
+            let command = if let Some(command) = multiline_command.take() {
+                command.push_line(line)
+            } else if let Some(command) = hidden_command(line).or(line.strip_prefix('$')) {
+                Multiline::begin(command.trim_start_matches('$').trim_start())
+            } else {
+                if !commands.is_empty() {
+                    expected_output.push_str(line);
+                    expected_output.push('\n');
                 }
                 continue;
-            }
-
-            if line.starts_with("$") {
-                let mut line = line.trim_start_matches('$').trim_start().to_string();
+            };
 
-                let open_string_idx = line.rfind("#\"");
-                let close_string_idx = line.rfind("\"#");
-                if open_string_idx.is_some() && open_string_idx.map(|idx| idx + 1) >= close_string_idx {
-                    multiline_command = Some(Multiline::ToEndString("\"#", line));
-                } else {
-                    let mark_string_count = line.matches("\"").count();
-                    if mark_string_count % 2 == 1 {
-                        multiline_command = Some(Multiline::ToEndString("\"", line));
-                    } else if line.ends_with('\\') {
-                        line.pop();
-                        multiline_command = Some(Multiline::WithLinesHasEnd("\\", line));
-                    } else {
-                        commands.push(line);
-                    }
-                }
-            } else if !commands.is_empty() {
-                expected_output.push_str(line);
-                expected_output.push('\n');
+            match command {
+                ControlFlow::Break(command) => commands.push(command),
+                ControlFlow::Continue(command) => multiline_command = Some(command),
             }
         }
 
@@ -145,6 +395,103 @@ impl TestCase {
                 source_line,
             },
             envs: Vec::new(),
+            file: None,
+            expected_file: None,
+            options: CaseOptions::default(),
+            normalizers: Vec::new(),
+            skip_reason: None,
+            interaction: Vec::new(),
+            jobs: Jobs::default(),
+            stub_url: None,
+            bin_dir: None,
+            allowed_envs: None,
+            recorder: Recorder::default(),
+        }
+    }
+
+    /// Parse the test case of an interactive block: the lines of the expected output starting with `> ` (see
+    /// [`INPUT_PREFIX`]) are typed into the command after the output preceding them is printed. The block must
+    /// run a single binary, the other commands of the block can only be builtins.
+    pub fn parse_interactive(
+        source: impl AsRef<str>,
+        source_path: Option<PathBuf>,
+        source_line: Option<usize>,
+    ) -> Result<Self, String> {
+        let source = source.as_ref();
+        let mut test_case = Self::parse(source, source_path, source_line);
+
+        let mut binaries = Vec::new();
+        for command in &test_case.commands {
+            binaries.extend(binary_commands(command).map_err(|err| format!("invalid command `{command}`: {err}"))?);
+        }
+        if binaries.len() > 1 {
+            return Err(format!(
+                "the replies would be typed into each of the binaries `{}`, an interactive block must run a single \
+                 binary",
+                binaries.join("`, `")
+            ));
+        }
+
+        // The first line of the code is the line after the fence
+        let mut input_lines = source
+            .lines()
+            .enumerate()
+            .skip_while(|(_, line)| !is_command_line(line))
+            .filter(|(_, line)| input_line(line).is_some())
+            .map(|(idx, _)| source_line.map(|line| line + idx + 1));
+
+        let mut prompt = String::new();
+        for line in test_case.output.text.split_inclusive('\n') {
+            if let Some(input) = input_line(line.trim_end_matches(['\n', '\r'])) {
+                test_case.interaction.push(InteractionStep {
+                    prompt: mem::take(&mut prompt),
+                    input: input.into(),
+                    source_line: input_lines.next().flatten(),
+                });
+            } else {
+                prompt.push_str(line);
+            }
+        }
+        test_case.output.text = prompt;
+        Ok(test_case)
+    }
+
+    /// Create a test case that writes the file without running any commands.
+    pub fn new_inline_file(
+        path: impl Into<String>,
+        content: impl Into<String>,
+        source_path: Option<PathBuf>,
+        source_line: Option<usize>,
+    ) -> Self {
+        Self {
+            output: ExpectedOutput {
+                text: String::new(),
+                source_path,
+                source_line,
+            },
+            file: Some(InlineFile {
+                path: path.into(),
+                content: content.into(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// Create a test case that checks the file content without running any commands.
+    pub fn new_expected_file(
+        path: impl Into<String>,
+        content: impl Into<String>,
+        source_path: Option<PathBuf>,
+        source_line: Option<usize>,
+    ) -> Self {
+        Self {
+            output: ExpectedOutput {
+                text: content.into(),
+                source_path,
+                source_line,
+            },
+            expected_file: Some(path.into()),
+            ..Default::default()
         }
     }
 
@@ -180,66 +527,537 @@ impl TestCase {
     }
 
     pub fn run(&self) -> error::Result<()> {
-        let mut root_dir = self.test_dir.clone().unwrap_or_default();
-        if !root_dir.exists() {
+        if let Some(reason) = &self.skip_reason {
+            let (source_path, source_line) = self.source_location();
+            log::info!("Skipped test case in source {source_path}:{source_line}: {reason}");
+            for command in &self.commands {
+                self.recorder.record(CommandReport {
+                    status: Status::Skipped,
+                    skip_reason: Some(reason.clone()),
+                    ..self.command_report(command)
+                });
+            }
+            return Ok(());
+        }
+
+        let test_dir = self.test_dir.clone().unwrap_or_default();
+        if !test_dir.exists() {
             return Err(TestError::Failed(format!(
                 "Root directory `{}` does not exist",
-                root_dir.display()
+                test_dir.display()
             )));
         }
+        let mut root_dir = match &self.options.working_dir {
+            Some(dir) => {
+                checked_join(&test_dir, &test_dir, dir).map_err(|err| self.locate_error(err, &format!("cwd={dir}")))?
+            },
+            None => test_dir.clone(),
+        };
+
+        if let Some(file) = &self.file {
+            write_inline_file(&test_dir, file).map_err(|err| self.locate_error(err, &format!("file={}", file.path)))?;
+        }
+
+        if let Some(path) = &self.expected_file {
+            let command = format!("expect-file={path}");
+            self.record(&command, |report| {
+                let file_path =
+                    checked_join(&test_dir, &test_dir, path).map_err(|err| self.locate_error(err, &command))?;
+                let content = fs::read_to_string(&file_path).map_err(|err| {
+                    TestError::Command(format!("Failed to read file `{}`: {err}", file_path.display()))
+                })?;
+                self.check_output(report, &test_dir, &content);
+                Ok(())
+            })?;
+        }
 
         for command in &self.commands {
-            match Cmd::parse(&root_dir, command) {
-                Ok(cmd) => match cmd.run()? {
-                    CmdResponse::Success => (),
-                    CmdResponse::ChangeDirTo(path) => root_dir = path,
-                    CmdResponse::Output(output) => self.assert_command_output(&root_dir, command, output),
-                },
-                Err(parts) => {
-                    if let [name, args @ ..] = &parts[..] {
-                        let mut cmd = if *name == self.cargo_bin_alias {
-                            let bin_name = if let Some(bin_name) = &self.cargo_bin_name {
-                                bin_name.clone()
-                            } else {
-                                env::var("CARGO_PKG_NAME")?
-                            };
-
-                            Command::cargo_bin(bin_name)?
-                        } else {
-                            Command::cargo_bin(name)?
-                        };
-
-                        let cmd_assert = cmd
-                            .envs(self.envs.iter().map(|(key, val)| (key, val)))
-                            .args(args)
-                            .current_dir(&root_dir)
-                            .assert();
-
-                        let stdout = separate_logs(&String::from_utf8_lossy(&cmd_assert.get_output().stdout));
-                        let stderr = separate_logs(&String::from_utf8_lossy(&cmd_assert.get_output().stderr));
-                        let full_output = format!("{stdout}{stderr}");
-
-                        self.assert_command_output(&root_dir, command, full_output);
-                    } else {
-                        return Err(TestError::Failed(format!("Invalid command `{command}`")));
+            let command = &self.expand(command);
+            self.record(command, |report| {
+                self.run_command_line(&test_dir, &mut root_dir, command, report)
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Run the command line and check its output, filling the exit status and the outputs into the report.
+    fn run_command_line(
+        &self,
+        test_dir: &Path,
+        root_dir: &mut PathBuf,
+        command: &str,
+        report: &mut CommandReport,
+    ) -> error::Result<()> {
+        let tokens = tokenize(command)?;
+        let list = split_command_list(&tokens);
+        let mut output: Option<String> = None;
+        let mut status = 0;
+
+        for (idx, (operator, pipeline)) in list.iter().enumerate() {
+            let is_skipped = match operator {
+                Some(ListOperator::And) => status != 0,
+                Some(ListOperator::Or) => status == 0,
+                Some(ListOperator::Sequence) | None => false,
+            };
+            if is_skipped {
+                continue;
+            }
+
+            let result = self
+                .run_pipeline(test_dir, root_dir, command, pipeline)
+                .map_err(|err| self.locate_error(err, command));
+
+            match result {
+                Ok((pipeline_status, pipeline_output)) => {
+                    status = pipeline_status;
+                    if let Some(text) = pipeline_output {
+                        output.get_or_insert_default().push_str(&text);
                     }
                 },
+                // A failed builtin command is handled like a non-zero exit status by the next `||`, skipping the
+                // `&&` pipelines before it, but not by a `||` after a `;`
+                Err(err)
+                    if list[idx + 1..]
+                        .iter()
+                        .find(|(operator, _)| *operator != Some(ListOperator::And))
+                        .is_some_and(|(operator, _)| *operator == Some(ListOperator::Or)) =>
+                {
+                    log::debug!("Command `{}` failed: {err}", tokens_source(command, pipeline));
+                    status = 1;
+                },
+                Err(err) => return Err(err),
+            }
+        }
+
+        report.exit_code = Some(status);
+        if let Some(output) = output {
+            self.check_output(report, root_dir, &output);
+        }
+        Ok(())
+    }
+
+    /// Run the command, then record its duration and its result, the failures are passed on after recording.
+    fn record(&self, command: &str, run: impl FnOnce(&mut CommandReport) -> error::Result<()>) -> error::Result<()> {
+        let mut report = self.command_report(command);
+        let started = Instant::now();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| run(&mut report)));
+        report.duration = started.elapsed();
+
+        let failure = match &result {
+            Ok(Ok(())) => None,
+            Ok(Err(err)) => Some(err.to_string()),
+            Err(panic) => Some(panic_message(panic.as_ref())),
+        };
+        report.status = if failure.is_some() {
+            Status::Failed
+        } else {
+            Status::Passed
+        };
+        report.failure = failure;
+        self.recorder.record(report);
+
+        result.unwrap_or_else(|panic| panic::resume_unwind(panic))
+    }
+
+    fn command_report(&self, command: &str) -> CommandReport {
+        CommandReport {
+            command: command.into(),
+            source_path: self.output.source_path.clone(),
+            source_line: self.output.source_line,
+            ..Default::default()
+        }
+    }
+
+    /// Run the pipeline and return its exit status and the output to check, if any.
+    fn run_pipeline(
+        &self,
+        test_dir: &Path,
+        root_dir: &mut PathBuf,
+        command: &str,
+        pipeline: &[Token],
+    ) -> error::Result<(i32, Option<String>)> {
+        if let [pipeline @ .., last] = pipeline
+            && last.operator() == Some(Operator::Background)
+        {
+            self.start_job(test_dir, root_dir, command, pipeline)?;
+            return Ok((0, None));
+        }
+
+        let stages = pipeline
+            .split(|token| token.operator() == Some(Operator::Pipe))
+            .collect::<Vec<_>>();
+        let mut stdin = None;
+
+        for (idx, stage) in stages.iter().enumerate() {
+            let (parts, redirects) = Redirects::split(test_dir, &root_dir, stage)?;
+
+            // Stages of a pipeline run in their own subshells, so `cd` does not affect the next commands
+            let output = if stages.len() > 1 {
+                self.run_command(test_dir, &mut root_dir.clone(), command, &parts, stdin.take())?
+            } else {
+                self.run_command(test_dir, root_dir, command, &parts, stdin.take())?
+            };
+
+            if idx + 1 < stages.len() {
+                let (stdout, stderr) = redirects.apply_streams(output.stdout, output.stderr)?;
+                if !stderr.is_empty() {
+                    log::debug!("Stderr of pipeline stage `{}`: {stderr}", tokens_source(command, stage));
+                }
+                stdin = Some(stdout);
+            } else {
+                let text = redirects.apply(output.stdout, output.stderr)?;
+                return Ok((output.status, text.filter(|_| output.is_checked)));
             }
         }
 
+        Err(TestError::Failed(format!("Invalid command `{command}`")))
+    }
+
+    /// Start the binary of the pipeline in the background and wait until it is ready, see [`CaseOptions::ready`].
+    fn start_job(&self, test_dir: &Path, root_dir: &Path, command: &str, pipeline: &[Token]) -> error::Result<()> {
+        let invalid_job = || {
+            TestError::Command(format!(
+                "Background command `{command}` must be a single binary without redirections"
+            ))
+        };
+        if pipeline.iter().any(|token| token.operator().is_some()) {
+            return Err(invalid_job());
+        }
+
+        let parts = pipeline.iter().filter_map(Token::word).collect::<Vec<_>>();
+        let [name, args @ ..] = parts.as_slice() else {
+            return Err(invalid_job());
+        };
+        if JobCmd::parse(&parts).is_some() || Cmd::parse(test_dir, root_dir, &parts)?.is_some() {
+            return Err(TestError::Command(format!(
+                "Builtin command `{name}` cannot run in the background"
+            )));
+        }
+
+        let cmd = self.cargo_bin(name)?;
+        let id = self.jobs.start(self.process_command(&cmd, args, root_dir), command)?;
+        if let Some(ready) = &self.options.ready {
+            let timeout = self.options.timeout.unwrap_or(DEFAULT_READY_TIMEOUT);
+            self.jobs.wait_ready(id, ready, test_dir, root_dir, timeout)?;
+        }
         Ok(())
     }
 
+    fn run_command(
+        &self,
+        test_dir: &Path,
+        root_dir: &mut PathBuf,
+        command: &str,
+        parts: &[&str],
+        stdin: Option<String>,
+    ) -> error::Result<CommandOutput> {
+        if let Some(job_cmd) = JobCmd::parse(parts) {
+            let (status, stdout) = self.jobs.run(job_cmd?, self.options.timeout)?;
+            // Only the output printed by the waited jobs is checked
+            return Ok(CommandOutput {
+                is_checked: !stdout.is_empty(),
+                stdout,
+                status,
+                ..Default::default()
+            });
+        }
+
+        if let ["stub-log", names @ ..] = parts {
+            let bin_dir = self
+                .bin_dir
+                .as_ref()
+                .ok_or_else(|| TestError::Command("No stub commands for `stub-log`".into()))?;
+            return Ok(CommandOutput {
+                stdout: command_stub::read_log(bin_dir, names)?,
+                is_checked: true,
+                ..Default::default()
+            });
+        }
+
+        if let Some(cmd) = Cmd::parse(test_dir, &root_dir, parts)? {
+            let output = match cmd.run(stdin.as_deref())? {
+                CmdResponse::Success => CommandOutput::default(),
+                CmdResponse::ChangeDirTo(path) => {
+                    *root_dir = path;
+                    CommandOutput::default()
+                },
+                CmdResponse::Output(stdout) => CommandOutput {
+                    stdout,
+                    is_checked: true,
+                    ..Default::default()
+                },
+            };
+            return Ok(output);
+        }
+
+        let [name, args @ ..] = parts else {
+            return Err(TestError::Failed(format!("Invalid command `{command}`")));
+        };
+
+        let mut cmd = self.cargo_bin(name)?;
+        if self.options.tty == Some(true) || !self.interaction.is_empty() {
+            let pty_cmd = self.process_command(&cmd, args, root_dir);
+            return if self.interaction.is_empty() {
+                self.run_in_pty(pty_cmd, command, stdin)
+            } else if stdin.is_some() {
+                Err(TestError::Command(format!(
+                    "Interactive command `{command}` cannot read the output of a pipeline"
+                )))
+            } else {
+                self.run_interactive(pty_cmd, command, root_dir)
+            };
+        }
+
+        if self.allowed_envs.is_some() {
+            cmd.env_clear();
+        }
+        cmd.envs(self.command_envs()).args(args).current_dir(&root_dir);
+        if let Some(stdin) = stdin {
+            cmd.write_stdin(stdin);
+        }
+        if let Some(timeout) = self.options.timeout {
+            cmd.timeout(timeout);
+        }
+        let started = Instant::now();
+        let cmd_assert = cmd.assert();
+        let status = cmd_assert.get_output().status.code();
+
+        // The command is killed when it reaches the timeout
+        if let Some(timeout) = self.options.timeout
+            && status.is_none()
+            && started.elapsed() >= timeout
+        {
+            return Err(TestError::Command(format!(
+                "Command `{command}` timed out after {}s",
+                timeout.as_secs_f64()
+            )));
+        }
+
+        Ok(CommandOutput {
+            stdout: separate_logs(&String::from_utf8_lossy(&cmd_assert.get_output().stdout)),
+            stderr: separate_logs(&String::from_utf8_lossy(&cmd_assert.get_output().stderr)),
+            status: status.unwrap_or(-1),
+            is_checked: true,
+        })
+    }
+
+    /// Find the binary of the command, the alias stands for the tested binary.
+    fn cargo_bin(&self, name: &str) -> error::Result<Command> {
+        let bin_name = if name == self.cargo_bin_alias {
+            match &self.cargo_bin_name {
+                Some(bin_name) => bin_name.clone(),
+                None => env::var("CARGO_PKG_NAME")?,
+            }
+        } else {
+            name.into()
+        };
+        Ok(Command::cargo_bin(bin_name)?)
+    }
+
+    /// Build the process of the binary found by [`Self::cargo_bin`] with the envs of the test case.
+    fn process_command(&self, cmd: &Command, args: &[&str], current_dir: &Path) -> process::Command {
+        let mut process_cmd = process::Command::new(cmd.get_program());
+        if self.allowed_envs.is_some() {
+            process_cmd.env_clear();
+        }
+        process_cmd
+            .envs(self.command_envs())
+            .args(args)
+            .current_dir(current_dir);
+        process_cmd
+    }
+
+    /// Run the command in a pseudo-terminal, its stdout and stderr are rendered together as the output.
+    fn run_in_pty(&self, cmd: process::Command, command: &str, stdin: Option<String>) -> error::Result<CommandOutput> {
+        let mut process = self.spawn_in_pty(cmd, command)?;
+        if let Some(stdin) = stdin {
+            // Ctrl-D ends the input of the terminal
+            process
+                .write_input(&format!("{stdin}\x04"))
+                .map_err(|err| TestError::Command(format!("Failed to write the input of `{command}`: {err}")))?;
+        }
+
+        self.finish_in_pty(process, command)
+    }
+
+    /// Run the command in a pseudo-terminal, typing each reply of the interaction as soon as the output since the
+    /// previous reply matches the prompt. The output after the last reply is returned.
+    fn run_interactive(
+        &self,
+        cmd: process::Command,
+        command: &str,
+        current_dir: &Path,
+    ) -> error::Result<CommandOutput> {
+        let test_dir = self.test_dir.clone().unwrap_or_default();
+        let context = NormalizeContext {
+            test_dir: &test_dir,
+            current_dir,
+        };
+        let timeout = self.options.timeout.unwrap_or(DEFAULT_PROMPT_TIMEOUT);
+        let mut process = self.spawn_in_pty(cmd, command)?;
+
+        for step in &self.interaction {
+            let expected = self.normalize(&self.expand(&step.prompt), &context);
+            let deadline = Instant::now() + timeout;
+            let mut output = Vec::new();
+
+            loop {
+                let rendered = render_terminal_output(&String::from_utf8_lossy(&output));
+                let actual = self.normalize(&self.options.ansi.unwrap_or_default().apply(&rendered), &context);
+                // The prompt usually ends with a space instead of the line ending of the code block
+                if actual.trim_end() == expected.trim_end() {
+                    break;
+                }
+
+                let reason = match process.read(Some(deadline)) {
+                    Read::Output(chunk) => {
+                        output.extend(chunk);
+                        continue;
+                    },
+                    Read::Eof => "the command exited".to_string(),
+                    Read::TimedOut => format!("no matching output after {}s", timeout.as_secs_f64()),
+                };
+                let _ = process.kill();
+                let (source_path, _) = self.source_location();
+                assert_eq!(
+                    actual.trim_end(),
+                    expected.trim_end(),
+                    "Prompt of command `{command}` before input in source {source_path}:{}: {reason}",
+                    step.source_line.unwrap_or_default()
+                );
+            }
+
+            process
+                .write_input(&format!("{}\n", step.input))
+                .map_err(|err| TestError::Command(format!("Failed to write the input of `{command}`: {err}")))?;
+        }
+
+        self.finish_in_pty(process, command)
+    }
+
+    fn spawn_in_pty(&self, cmd: process::Command, command: &str) -> error::Result<PtyProcess> {
+        PtyProcess::spawn(cmd, self.options.tty_size.unwrap_or_default())
+            .map_err(|err| TestError::Command(format!("Failed to run `{command}` in a terminal: {err}")))
+    }
+
+    /// Read the rest of the output of the process until it exits.
+    fn finish_in_pty(&self, mut process: PtyProcess, command: &str) -> error::Result<CommandOutput> {
+        let deadline = self.options.timeout.map(|timeout| Instant::now() + timeout);
+        let Some(output) = process.read_to_end(deadline) else {
+            let _ = process.kill();
+            return Err(TestError::Command(format!(
+                "Command `{command}` timed out after {}s",
+                self.options.timeout.unwrap_or_default().as_secs_f64()
+            )));
+        };
+        let status = process.wait()?;
+
+        Ok(CommandOutput {
+            stdout: separate_logs(&render_terminal_output(&String::from_utf8_lossy(&output))),
+            stderr: String::new(),
+            status: status.code().unwrap_or(-1),
+            is_checked: true,
+        })
+    }
+
     pub fn assert_command_output(&self, root_dir: impl AsRef<Path>, command: impl AsRef<str>, output: impl AsRef<str>) {
-        let root_dir = root_dir.as_ref();
-        let command = command.as_ref();
-        let output = output.as_ref();
+        let (actual, expected) = self.normalize_outputs(root_dir.as_ref(), output.as_ref());
+        self.assert_outputs(command.as_ref(), &actual, &expected);
+    }
 
-        let expected_output = self
-            .output
-            .text
-            .replace("${current_dir_path}", &root_dir.to_string_lossy());
+    /// Record the normalized outputs in the report, then assert them.
+    fn check_output(&self, report: &mut CommandReport, root_dir: &Path, output: &str) {
+        let (actual, expected) = self.normalize_outputs(root_dir, output);
+        report.actual = Some(actual.clone());
+        report.expected = Some(expected.clone());
+        self.assert_outputs(&report.command, &actual, &expected);
+    }
+
+    /// Normalize the actual output after handling its ANSI sequences and the expected output.
+    fn normalize_outputs(&self, root_dir: &Path, output: &str) -> (String, String) {
+        let test_dir = self.test_dir.clone().unwrap_or_default();
+        let context = NormalizeContext {
+            test_dir: &test_dir,
+            current_dir: root_dir,
+        };
+        let normalized_output = self.normalize(&self.options.ansi.unwrap_or_default().apply(output), &context);
+        let expected_output = self.normalize(&self.expand(&self.output.text), &context);
+
+        (normalized_output, expected_output)
+    }
+
+    fn assert_outputs(&self, command: &str, normalized_output: &str, expected_output: &str) {
+        let (source_path, source_line) = self.source_location();
+
+        match self.options.match_mode.unwrap_or_default() {
+            MatchMode::Exact => assert_eq!(
+                normalized_output, expected_output,
+                "Command `{command}` in source {source_path}:{source_line}"
+            ),
+            // The trailing newline of the code block does not need to be at the end of the output
+            MatchMode::Contains => assert!(
+                normalized_output.contains(expected_output.trim_end_matches('\n')),
+                "Command `{command}` in source {source_path}:{source_line}\n  output: {normalized_output:?}\n  does not contain: {expected_output:?}"
+            ),
+            MatchMode::Ignore => {},
+        }
+    }
+
+    /// Envs of the commands, the envs of the directives override the other ones. If the inherited environment is
+    /// cleared, the allowed envs of the test process go first.
+    fn command_envs(&self) -> Vec<(OsString, OsString)> {
+        let allowed_envs = self.allowed_envs.iter().flatten().filter_map(|name| {
+            let val = env::var_os(name)?;
+            Some((OsString::from(name), val))
+        });
+        let envs = self.envs.iter().map(|(key, val)| {
+            let val = val.to_str().map_or_else(|| val.clone(), |val| self.expand(val).into());
+            (key.clone(), val)
+        });
+        let option_envs = self
+            .options
+            .envs
+            .iter()
+            .map(|(key, val)| (key.into(), self.expand(val).into()));
+        let mut envs = allowed_envs
+            .chain(envs)
+            .chain(option_envs)
+            .collect::<Vec<(OsString, OsString)>>();
 
+        if let Some(bin_dir) = &self.bin_dir {
+            let path = envs
+                .iter()
+                .rfind(|(key, _)| key == "PATH")
+                .map(|(_, path)| path.clone())
+                .or_else(|| env::var_os("PATH"))
+                .unwrap_or_default();
+            let paths = iter::once(bin_dir.clone()).chain(env::split_paths(&path));
+            if let Ok(path) = env::join_paths(paths) {
+                envs.push(("PATH".into(), path));
+            }
+        }
+        envs
+    }
+
+    /// Substitute the URL of the stub server for the `${stub_url}` placeholder.
+    fn expand(&self, text: &str) -> String {
+        match &self.stub_url {
+            Some(url) => text.replace(STUB_URL_PLACEHOLDER, url),
+            None => text.into(),
+        }
+    }
+
+    /// Apply the default normalizers and then the normalizers of the test case.
+    fn normalize(&self, text: &str, context: &NormalizeContext<'_>) -> String {
+        let mut text = text.to_string();
+        for normalizer in default_normalizers().iter().chain(&self.normalizers) {
+            text = normalizer.normalize(&text, context);
+        }
+        text
+    }
+
+    fn source_location(&self) -> (String, usize) {
         let source_path = self
             .output
             .source_path
@@ -248,16 +1066,24 @@ impl TestCase {
             .unwrap_or_default();
         let source_line = self.output.source_line.unwrap_or_default();
 
-        // On macOS, temporary directories may appear with a `/private` prefix,
-        // e.g., `/private/var/folders/...`, which causes mismatch with expected output
-        // defined as `/var/folders/...`. To ensure cross-platform consistency,
-        // we normalize such paths in test output comparison.
-        let normalized_output = output.replace("/private/var/", "/var/");
+        (source_path, source_line)
+    }
 
-        assert_eq!(
-            normalized_output, expected_output,
-            "Command `{command}` in source {source_path}:{source_line}"
-        );
+    /// Fill the command and its source location into the error, if the error has them.
+    fn locate_error(&self, err: TestError, command: &str) -> TestError {
+        match err {
+            TestError::SandboxEscape { arg, path, .. } => {
+                let (source_path, source_line) = self.source_location();
+                TestError::SandboxEscape {
+                    arg,
+                    path,
+                    command: command.into(),
+                    source_path,
+                    source_line,
+                }
+            },
+            err => err,
+        }
     }
 }
 
@@ -266,77 +1092,357 @@ pub fn parse_markdown_tests(
     cargo_bin_alias: Option<String>,
     cargo_bin_name: Option<String>,
     vars: Option<impl IntoIterator<Item = (impl Into<OsString>, impl Into<OsString>)> + Clone>,
-) -> io::Result<Vec<TestSection>> {
+) -> error::Result<Vec<TestSection>> {
     let md_file_path = md_file_path.as_ref();
     let content = fs::read_to_string(md_file_path)?;
-    let parser = Parser::new(&content);
+    parse_markdown(md_file_path, &content, cargo_bin_alias, cargo_bin_name, vars, &[])
+}
+
+/// Parse the test sections of the markdown `content` read from `md_file_path`, skipping its front matter. Only the
+/// code blocks of the `languages` contain commands, see [`DEFAULT_LANGUAGES`] if empty.
+pub fn parse_markdown(
+    md_file_path: &Path,
+    content: &str,
+    cargo_bin_alias: Option<String>,
+    cargo_bin_name: Option<String>,
+    vars: Option<impl IntoIterator<Item = (impl Into<OsString>, impl Into<OsString>)> + Clone>,
+    languages: &[String],
+) -> error::Result<Vec<TestSection>> {
+    let body_start = front_matter(content).map_or(0, |front_matter| front_matter.end);
+    let parser = Parser::new(&content[body_start..]);
+    let line_at = |offset: usize| content[..offset].matches('\n').count() + 1;
 
     let mut sections = Vec::new();
-    let mut cases = Vec::new();
-    let mut test_case = None;
-    let mut test_case_start_line = None;
-    let mut section_title = String::new();
-    let mut in_test_case_code_block = false;
+    // Fixtures, setup and teardown blocks before the first section apply to every section
+    let mut file_hooks = TestSection::default();
+    let mut section: Option<TestSection> = None;
+    let mut code_block: Option<CodeBlock> = None;
+    let mut html_block: Option<(String, usize)> = None;
     let mut in_section_heading = false;
+    let mut has_heading = false;
+    let mut file_options = CaseOptions::default();
+    let mut section_options = CaseOptions::default();
+    let mut next_options = CaseOptions::default();
 
     for (event, range) in parser.into_offset_iter() {
+        let range = range.start + body_start..range.end + body_start;
         match event {
-            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang)))
-                if lang.as_ref() == "sh" || lang.as_ref() == "shell" =>
-            {
-                in_test_case_code_block = true;
-                test_case_start_line = Some(content.split_at(range.start).0.lines().count() + 1);
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
+                let info = BlockInfo::parse(&info, languages).map_err(|reason| TestError::Directive {
+                    directive: info.to_string(),
+                    reason,
+                    source_path: md_file_path.display().to_string(),
+                    source_line: line_at(range.start),
+                })?;
+                if info.is_test_case(languages)
+                    || info.file.is_some()
+                    || info.expect_file.is_some()
+                    || info.stub
+                    || info.command.is_some()
+                {
+                    code_block = Some(CodeBlock {
+                        info,
+                        start_line: line_at(range.start),
+                        text: String::new(),
+                    });
+                }
             },
-            Event::Text(text) if in_test_case_code_block => {
-                let mut new_test_case = TestCase::parse(text, Some(md_file_path.into()), test_case_start_line);
-                if let Some(alias) = cargo_bin_alias.clone() {
-                    new_test_case.set_cargo_bin_alias(alias, cargo_bin_name.clone());
+            Event::Text(text) if code_block.is_some() => {
+                if let Some(code_block) = &mut code_block {
+                    code_block.text.push_str(&text);
+                }
+            },
+            Event::End(TagEnd::CodeBlock) => {
+                let Some(CodeBlock { info, start_line, text }) = code_block.take() else {
+                    continue;
+                };
+
+                if info.stub {
+                    let mut stub = HttpStub::parse(&text).map_err(|reason| TestError::Directive {
+                        directive: "stub".into(),
+                        reason,
+                        source_path: md_file_path.display().to_string(),
+                        source_line: start_line,
+                    })?;
+                    stub.required = info.required;
+                    stub.source_path = Some(md_file_path.into());
+                    stub.source_line = Some(start_line);
+
+                    // Stubs before the first section apply to every section
+                    let target = if has_heading {
+                        section.get_or_insert_with(TestSection::default)
+                    } else {
+                        &mut file_hooks
+                    };
+                    target.stubs.push(stub);
+                    continue;
                 }
-                if let Some(vars) = vars.clone() {
-                    new_test_case.push_envs(vars);
+
+                if let Some(name) = info.command {
+                    // Shell blocks are scripts, the other blocks are the output of the command
+                    let stub = if ["sh", "shell", "bash"].contains(&info.lang.as_str()) || text.starts_with("#!") {
+                        CommandStub::script(name, text)
+                    } else {
+                        CommandStub::output(name, text, info.exit_code.unwrap_or(0))
+                    };
+                    let target = if has_heading {
+                        section.get_or_insert_with(TestSection::default)
+                    } else {
+                        &mut file_hooks
+                    };
+                    target.command_stubs.push(stub);
+                    continue;
                 }
 
-                test_case = Some(new_test_case);
-            },
-            Event::End(TagEnd::CodeBlock) if in_test_case_code_block => {
-                if let Some(test) = test_case.take() {
-                    cases.push(test);
+                let mut test_case = if let Some(path) = info.file {
+                    TestCase::new_inline_file(path, text, Some(md_file_path.into()), Some(start_line))
+                } else if let Some(path) = info.expect_file {
+                    TestCase::new_expected_file(path, text, Some(md_file_path.into()), Some(start_line))
+                } else {
+                    let mut test_case = if info.interactive {
+                        TestCase::parse_interactive(text, Some(md_file_path.into()), Some(start_line)).map_err(
+                            |reason| TestError::Directive {
+                                directive: "interactive".into(),
+                                reason,
+                                source_path: md_file_path.display().to_string(),
+                                source_line: start_line,
+                            },
+                        )?
+                    } else {
+                        TestCase::parse(text, Some(md_file_path.into()), Some(start_line))
+                    };
+                    if let Some(alias) = cargo_bin_alias.clone() {
+                        test_case.set_cargo_bin_alias(alias, cargo_bin_name.clone());
+                    }
+                    if let Some(vars) = vars.clone() {
+                        test_case.push_envs(vars);
+                    }
+                    test_case
+                };
+                test_case.options = info.options.or(&mem::take(&mut next_options).or(&section_options));
+
+                let target = if info.hook.is_some() && !has_heading {
+                    &mut file_hooks
+                } else {
+                    section.get_or_insert_with(TestSection::default)
+                };
+                match info.hook {
+                    Some(SectionHook::Setup) => target.setup.push(test_case),
+                    Some(SectionHook::Teardown) => target.teardown.push(test_case),
+                    None => target.cases.push(test_case),
                 }
-                in_test_case_code_block = false;
             },
             Event::Start(Tag::Heading {
                 level: HeadingLevel::H1,
                 ..
             }) => {
-                if !cases.is_empty() {
-                    sections.push(TestSection {
-                        title: mem::take(&mut section_title),
-                        cases,
-                    });
-                    cases = Vec::new();
+                if let Some(section) = section.take().filter(|section| !section.cases.is_empty()) {
+                    sections.push(section);
                 }
+                section = Some(TestSection::default());
+                section_options = CaseOptions::default();
                 in_section_heading = true;
+                has_heading = true;
             },
             Event::Text(text) if in_section_heading => {
-                section_title = text.to_string();
+                if let Some(section) = &mut section {
+                    section.title = text.to_string();
+                }
             },
             Event::End(TagEnd::Heading(HeadingLevel::H1)) if in_section_heading => {
                 in_section_heading = false;
             },
+            Event::Start(Tag::HtmlBlock) => html_block = Some((String::new(), line_at(range.start))),
+            // The parser emits an HTML event per line of the block
+            Event::Html(html) => {
+                if let Some((text, _)) = &mut html_block {
+                    text.push_str(&html);
+                }
+            },
+            Event::End(TagEnd::HtmlBlock) => {
+                let Some((html, start_line)) = html_block.take() else {
+                    continue;
+                };
+                if let Some(fixture) = parse_fixture_directive(&html) {
+                    let md_dir = md_file_path.parent().unwrap_or(Path::new(""));
+                    // Fixtures before the first section apply to every section
+                    let target = if has_heading {
+                        section.get_or_insert_with(TestSection::default)
+                    } else {
+                        &mut file_hooks
+                    };
+                    target.fixtures.push(md_dir.join(fixture));
+                } else if let Some(directive) = parse_directive(&html) {
+                    let directive = directive.map_err(|reason| TestError::Directive {
+                        directive: html.trim().into(),
+                        reason,
+                        source_path: md_file_path.display().to_string(),
+                        source_line: start_line,
+                    })?;
+                    let options = match directive.scope {
+                        Scope::NextBlock => &mut next_options,
+                        Scope::Section => &mut section_options,
+                        Scope::File => &mut file_options,
+                    };
+                    *options = directive.options.or(options);
+                }
+            },
+            Event::InlineHtml(html) if parse_fixture_directive(&html).is_some() || parse_directive(&html).is_some() => {
+                return Err(TestError::Directive {
+                    directive: html.trim().into(),
+                    reason: "a directive must be on its own lines, separated from the text by empty lines".into(),
+                    source_path: md_file_path.display().to_string(),
+                    source_line: line_at(range.start),
+                });
+            },
             _ => {},
         }
     }
 
-    if !cases.is_empty() {
-        sections.push(TestSection {
-            title: section_title,
-            cases,
-        });
+    if let Some(section) = section.filter(|section| !section.cases.is_empty()) {
+        sections.push(section);
+    }
+
+    for section in &mut sections {
+        section.fixtures.splice(0..0, file_hooks.fixtures.iter().cloned());
+        section.setup.splice(0..0, file_hooks.setup.iter().cloned());
+        section.teardown.extend(file_hooks.teardown.iter().cloned());
+        section.stubs.extend(file_hooks.stubs.iter().cloned());
+        section
+            .command_stubs
+            .splice(0..0, file_hooks.command_stubs.iter().cloned());
+
+        for test_case in section.all_cases_mut() {
+            test_case.options = mem::take(&mut test_case.options).or(&file_options);
+        }
     }
 
     Ok(sections)
 }
 
+/// Remove the hidden command lines (see [`HIDDEN_COMMAND_PREFIX`]) from the code blocks with commands of the
+/// markdown, e.g. to generate user-facing documentation from the test files. The `languages` are the ones configured
+/// for the `Tester`, if empty the languages of the front matter or the [`DEFAULT_LANGUAGES`] are used.
+pub fn strip_hidden_commands(markdown: &str, languages: &[String]) -> String {
+    let front_matter_languages;
+    let languages = if languages.is_empty() {
+        front_matter_languages = front_matter(markdown)
+            .and_then(|front_matter| front_matter.parse_config().ok())
+            .map(|config| config.languages)
+            .unwrap_or_default();
+        &front_matter_languages
+    } else {
+        languages
+    };
+
+    let mut code_ranges = Vec::new();
+    let mut in_test_case = false;
+    for (event, range) in Parser::new(markdown).into_offset_iter() {
+        match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
+                in_test_case = BlockInfo::parse(&info, languages).is_ok_and(|info| info.is_test_case(languages));
+            },
+            Event::Text(_) if in_test_case => code_ranges.push(range),
+            Event::End(TagEnd::CodeBlock) => in_test_case = false,
+            _ => {},
+        }
+    }
+
+    let mut stripped = String::with_capacity(markdown.len());
+    let mut line_start = 0;
+    // The continuation lines of a hidden command are hidden too
+    let mut hidden_multiline: Option<Multiline> = None;
+    for line in markdown.split_inclusive('\n') {
+        let is_code = code_ranges.iter().any(|range| range.contains(&line_start));
+        let text = line.trim_end_matches(['\n', '\r']);
+        line_start += line.len();
+
+        let command = match hidden_multiline.take() {
+            Some(command) if is_code => command.push_line(text),
+            _ => match hidden_command(text) {
+                Some(command) if is_code => Multiline::begin(command.trim_start_matches('$').trim_start()),
+                _ => {
+                    stripped.push_str(line);
+                    continue;
+                },
+            },
+        };
+        hidden_multiline = command.continue_value();
+    }
+    stripped
+}
+
+fn is_command_line(line: &str) -> bool {
+    hidden_command(line).is_some() || line.starts_with('$')
+}
+
+/// Commands of the command line that run binaries rather than builtins.
+fn binary_commands(command: &str) -> Result<Vec<&str>, LexError> {
+    let tokens = tokenize(command)?;
+    let commands = split_command_list(&tokens)
+        .into_iter()
+        .flat_map(|(_, pipeline)| pipeline.split(|token| token.operator() == Some(Operator::Pipe)))
+        .filter(|stage| {
+            stage
+                .first()
+                .and_then(Token::word)
+                .is_some_and(|name| !BUILTIN_COMMANDS.contains(&name))
+        })
+        .map(|stage| tokens_source(command, stage).trim_end_matches(['&', ' ']))
+        .collect();
+    Ok(commands)
+}
+
+/// Command of the hidden command line, which may be indented unlike the visible command lines.
+fn hidden_command(line: &str) -> Option<&str> {
+    line.trim_start().strip_prefix(HIDDEN_COMMAND_PREFIX)
+}
+
+/// Text typed by the `> text` line, an empty line for a single `>`.
+fn input_line(line: &str) -> Option<&str> {
+    let input = line.strip_prefix(INPUT_PREFIX)?;
+    if input.is_empty() {
+        Some(input)
+    } else {
+        input.strip_prefix(' ')
+    }
+}
+
+fn write_inline_file(test_dir: &Path, file: &InlineFile) -> error::Result<()> {
+    let path = checked_join(test_dir, test_dir, &file.path)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    fs::write(&path, &file.content)
+        .map_err(|err| TestError::Command(format!("Failed to write file `{}`: {err}", path.display())))
+}
+
+/// Parse the `<!-- fixture: path -->` comment and return the fixture path.
+fn parse_fixture_directive(html: &str) -> Option<&str> {
+    let comment = html.trim().strip_prefix("<!--")?.strip_suffix("-->")?;
+    let fixture = comment.trim().strip_prefix("fixture:")?.trim();
+    (!fixture.is_empty()).then_some(fixture)
+}
+
+/// Recursively copy the content of the `from` directory into the `to` directory.
+pub fn copy_dir_content(from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<()> {
+    let to = to.as_ref();
+    fs::create_dir_all(to)?;
+
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+
+        if entry.path().is_dir() {
+            copy_dir_content(entry.path(), target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
 fn separate_logs(source: &str) -> String {
     let mut outputs = source
         .lines()
@@ -359,7 +1465,16 @@ fn separate_logs(source: &str) -> String {
 
 #[cfg(test)]
 mod tests {
-    use super::TestCase;
+    use std::panic;
+    use std::time::Duration;
+
+    use temp_testdir::TempDir;
+
+    use super::{
+        BlockInfo, Condition, InteractionStep, SectionHook, TestCase, parse_fixture_directive, strip_hidden_commands,
+    };
+    use crate::error::TestError;
+    use crate::pty::PtySize;
 
     #[test]
     fn parse_test_case() {
@@ -410,4 +1525,359 @@ Error: destination `~/test A` already exists
             "    Creating `test A` project\nError: destination `~/test A` already exists\n"
         );
     }
+
+    #[test]
+    fn parse_multiline_commands() {
+        let test = TestCase::parse(
+            r##"
+$ echo r#"
+first "line"
+second line
+"# > out.txt
+$ echo 'single
+quoted' "double
+quoted"
+$ todo new \
+    "test A"
+output
+"##,
+            None,
+            None,
+        );
+
+        assert_eq!(test.commands, vec![
+            "echo r#\"\nfirst \"line\"\nsecond line\n\"# > out.txt",
+            "echo 'single\nquoted' \"double\nquoted\"",
+            "todo new \n    \"test A\"",
+        ]);
+        assert_eq!(test.output.text, "output\n");
+    }
+
+    #[test]
+    fn run_pipeline() {
+        let test_dir = TempDir::default();
+
+        TestCase::parse(
+            r#"
+$ echo "piped text" | cat | cat > out.txt
+$ cat out.txt | cat
+piped text"#,
+            None,
+            None,
+        )
+        .with_test_dir(test_dir.as_os_str())
+        .run()
+        .unwrap();
+    }
+
+    #[test]
+    fn run_command_list() {
+        let test_dir = TempDir::default();
+
+        TestCase::parse(
+            r#"
+$ mkdir a && cd a && mkdir b; ls .
+b
+"#,
+            None,
+            None,
+        )
+        .with_test_dir(test_dir.as_os_str())
+        .run()
+        .unwrap();
+
+        TestCase::parse(
+            r#"
+$ rm missing || echo fallback && echo " done"; cat missing && echo unreachable || echo " handled"
+fallback done handled"#,
+            None,
+            None,
+        )
+        .with_test_dir(test_dir.as_os_str())
+        .run()
+        .unwrap();
+
+        let result = TestCase::parse("$ mkdir c && cat missing; echo unreachable", None, None)
+            .with_test_dir(test_dir.as_os_str())
+            .run();
+        assert!(result.is_err());
+
+        // Only the next `||` handles the failed builtin, not one after `;`
+        let result = TestCase::parse("$ cd missing; ls . || echo handled", None, None)
+            .with_test_dir(test_dir.as_os_str())
+            .run();
+        assert!(matches!(result, Err(TestError::Command(_))), "{result:?}");
+    }
+
+    #[test]
+    fn run_sandbox_escape() {
+        let test_dir = TempDir::default();
+
+        TestCase::parse("$ mkdir a && cd a && cd .. && ls ./a\n\n", None, None)
+            .with_test_dir(test_dir.as_os_str())
+            .run()
+            .unwrap();
+
+        let result = TestCase::parse("$ cat ../secret", Some("test.md".into()), Some(3))
+            .with_test_dir(test_dir.as_os_str())
+            .run();
+        assert!(
+            matches!(
+                &result,
+                Err(TestError::SandboxEscape { arg, command, source_path, source_line: 3, .. })
+                    if arg == "../secret" && command == "cat ../secret" && source_path == "test.md"
+            ),
+            "{result:?}"
+        );
+
+        let result = TestCase::parse("$ cd a && echo text > ../../out.txt", None, None)
+            .with_test_dir(test_dir.as_os_str())
+            .run();
+        assert!(matches!(result, Err(TestError::SandboxEscape { .. })), "{result:?}");
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("/", test_dir.join("escape")).unwrap();
+            std::os::unix::fs::symlink("/nonexistent", test_dir.join("dangling")).unwrap();
+
+            for command in ["$ ls escape/tmp", "$ rm escape/tmp/file", "$ mkdir dangling/dir"] {
+                let result = TestCase::parse(command, None, None)
+                    .with_test_dir(test_dir.as_os_str())
+                    .run();
+                assert!(matches!(result, Err(TestError::SandboxEscape { .. })), "{result:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn parse_fixture() {
+        assert_eq!(
+            parse_fixture_directive("<!-- fixture: sample-project -->\n"),
+            Some("sample-project")
+        );
+        assert_eq!(parse_fixture_directive("<!--fixture:a/b-->"), Some("a/b"));
+        assert_eq!(parse_fixture_directive("<!-- fixture: -->"), None);
+        assert_eq!(parse_fixture_directive("<!-- some comment -->"), None);
+        assert_eq!(parse_fixture_directive("<div>fixture: a</div>"), None);
+    }
+
+    #[test]
+    fn parse_hidden_commands() {
+        let case = TestCase::parse("#$ mkdir src\n$ ls .\nsrc\n#$ rm src\n", None, None);
+        assert_eq!(case.commands, vec!["mkdir src", "ls .", "rm src"]);
+        assert_eq!(case.output.text, "src\n");
+        let case = TestCase::parse("  #$ mkdir src\n$ ls .\nsrc\n", None, None);
+        assert_eq!(case.commands, vec!["mkdir src", "ls ."]);
+        assert_eq!(case.output.text, "src\n");
+
+        let markdown = r#"# Title
+
+#$ not a command
+
+```sh
+#$ mkdir src
+  #$ mkdir tests
+$ ls .
+src
+```
+
+```rust
+#$ not a command
+```
+"#;
+        assert_eq!(
+            strip_hidden_commands(markdown, &[]),
+            r#"# Title
+
+#$ not a command
+
+```sh
+$ ls .
+src
+```
+
+```rust
+#$ not a command
+```
+"#
+        );
+
+        let markdown = "```console\n#$ mkdir src\n$ ls .\n```\n";
+        assert_eq!(strip_hidden_commands(markdown, &[]), markdown);
+        assert_eq!(
+            strip_hidden_commands(markdown, &["console".into()]),
+            "```console\n$ ls .\n```\n"
+        );
+        let markdown = format!("---\nlanguages: [console]\n---\n{markdown}");
+        assert_eq!(
+            strip_hidden_commands(&markdown, &[]),
+            "---\nlanguages: [console]\n---\n```console\n$ ls .\n```\n"
+        );
+
+        let markdown =
+            "```sh\n#$ mkdir \\\n  src \\\n  tests\n#$ echo \"a\nb\" > c.txt\n$ ls .\nc.txt src tests\n```\n";
+        assert_eq!(
+            TestCase::parse(markdown.lines().skip(1).collect::<Vec<_>>().join("\n"), None, None).commands,
+            vec!["mkdir \n  src \n  tests", "echo \"a\nb\" > c.txt", "ls ."]
+        );
+        assert_eq!(
+            strip_hidden_commands(markdown, &[]),
+            "```sh\n$ ls .\nc.txt src tests\n```\n"
+        );
+    }
+
+    #[test]
+    fn parse_interactive_case() {
+        let source = "$ todo delete \"project A\"\nDelete project A? [y/N]\n> y\nName to confirm:\n>\nDeleted\n";
+        let case = TestCase::parse_interactive(source, None, Some(10)).unwrap();
+        assert_eq!(case.commands, vec!["todo delete \"project A\""]);
+        assert_eq!(case.interaction, [
+            InteractionStep {
+                prompt: "Delete project A? [y/N]\n".into(),
+                input: "y".into(),
+                source_line: Some(13),
+            },
+            InteractionStep {
+                prompt: "Name to confirm:\n".into(),
+                input: "".into(),
+                source_line: Some(15),
+            },
+        ]);
+        assert_eq!(case.output.text, "Deleted\n");
+        assert!(TestCase::parse("$ ls\n> y\n", None, None).interaction.is_empty());
+
+        let case = TestCase::parse_interactive("$ mkdir a && cd a\n$ todo init | cat\n> y\n", None, None).unwrap();
+        assert_eq!(case.interaction.len(), 1);
+        let err = TestCase::parse_interactive("$ todo add x\n$ todo rm x\n> y\n", None, None).unwrap_err();
+        assert!(err.contains("`todo add x`, `todo rm x`"), "{err}");
+        let err = TestCase::parse_interactive("$ todo init && todo rm x\n> y\n", None, None).unwrap_err();
+        assert!(err.contains("`todo init`, `todo rm x`"), "{err}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_interactive_command() {
+        use std::process::Command;
+
+        let test_dir = TempDir::default();
+        let script = r#"printf "Delete? [y/N] "; read answer; echo "answer: $answer"; printf "Sure? "; read answer"#;
+        let run = |source: &str| {
+            let mut case = TestCase::parse_interactive(source, None, Some(1))
+                .unwrap()
+                .with_test_dir(test_dir.as_os_str());
+            case.options.timeout = Some(Duration::from_secs(1));
+            let mut cmd = Command::new("sh");
+            cmd.args(["-c", script]);
+            case.run_interactive(cmd, "sh", &test_dir).unwrap().stdout
+        };
+
+        assert_eq!(run("$ sh\nDelete? [y/N]\n> y\nanswer: y\nSure?\n> yes\n"), "");
+
+        let result = panic::catch_unwind(|| run("$ sh\nDelete? [Y/n]\n> y\n"));
+        let message = *result.unwrap_err().downcast::<String>().unwrap();
+        assert!(message.contains("no matching output after 1s"), "{message}");
+    }
+
+    #[test]
+    fn parse_block_info() {
+        let cases = [
+            ("sh", "sh", None, None),
+            ("shell", "shell", None, None),
+            ("toml file=Project.toml", "toml", Some("Project.toml"), None),
+            ("toml,file=Project.toml", "toml", Some("Project.toml"), None),
+            (
+                "toml file=project A/Project.toml",
+                "toml",
+                Some("project A/Project.toml"),
+                None,
+            ),
+            (
+                "toml file=\"project A/Project.toml\"",
+                "toml",
+                Some("project A/Project.toml"),
+                None,
+            ),
+            ("toml profile=x", "toml", None, None),
+            ("toml file=", "toml", None, None),
+            (
+                "toml expect-file=project A/Project.toml",
+                "toml",
+                None,
+                Some("project A/Project.toml"),
+            ),
+            ("", "", None, None),
+        ];
+
+        for (info, lang, file, expect_file) in cases {
+            assert_eq!(
+                BlockInfo::parse(info, &[]),
+                Ok(BlockInfo {
+                    lang: lang.into(),
+                    file: file.map(Into::into),
+                    expect_file: expect_file.map(Into::into),
+                    ..Default::default()
+                }),
+                "info: {info:?}"
+            );
+        }
+
+        let hooks = [
+            ("sh,setup", Some(SectionHook::Setup)),
+            ("sh teardown", Some(SectionHook::Teardown)),
+            ("toml,setup file=todo.toml", Some(SectionHook::Setup)),
+            ("toml file=setup", None),
+            ("sh,feature=sqlite,teardown", Some(SectionHook::Teardown)),
+            ("setup", None),
+        ];
+
+        for (info, hook) in hooks {
+            assert_eq!(BlockInfo::parse(info, &[]).unwrap().hook, hook, "info: {info:?}");
+        }
+
+        let info = BlockInfo::parse("sh,cfg(any(unix, windows)) env=CI,feature=sqlite file=a b", &[]).unwrap();
+        assert_eq!(info.file.as_deref(), Some("a b"));
+        assert_eq!(info.options.conditions, [
+            Condition::parse("cfg(any(unix, windows))").unwrap().unwrap(),
+            Condition::Env("CI".into()),
+            Condition::Feature("sqlite".into()),
+        ]);
+        assert!(BlockInfo::parse("sh,cfg(unix", &[]).is_err());
+
+        let info = BlockInfo::parse("sh,tty=120x40", &[]).unwrap();
+        assert_eq!(info.options.tty_size, Some(PtySize { cols: 120, rows: 40 }));
+        assert_eq!(BlockInfo::parse("sh tty", &[]).unwrap().options.tty, Some(true));
+
+        let info = BlockInfo::parse("text,command=editor,exit=1", &[]).unwrap();
+        assert_eq!((info.command.as_deref(), info.exit_code), (Some("editor"), Some(1)));
+        assert!(BlockInfo::parse("text,command=editor,exit=no", &[]).is_err());
+
+        assert_eq!(
+            BlockInfo::parse("sh,setpu", &[]),
+            Err("unknown attribute `setpu` of the `sh` block".into())
+        );
+        assert!(BlockInfo::parse("http,stub,requred", &[]).is_err());
+        assert!(BlockInfo::parse("console,setpu", &[]).is_ok());
+        assert!(BlockInfo::parse("console,setpu", &["console".into()]).is_err());
+        assert!(BlockInfo::parse("sh fiel=x", &[]).is_err());
+    }
+
+    #[test]
+    fn keep_allowed_envs() {
+        let mut case = TestCase::parse("$ todo sync", None, None).with_env("HOME", "/tmp/home");
+        case.options.envs.push(("TZ".into(), "CET".into()));
+        case.allowed_envs = Some(vec!["CARGO_PKG_NAME".into(), "MD_CLI_TEST_UNSET_VAR".into()]);
+
+        let keys = case.command_envs().into_iter().map(|(key, _)| key).collect::<Vec<_>>();
+        assert_eq!(keys, ["CARGO_PKG_NAME", "HOME", "TZ"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn prepend_stub_dir_to_path() {
+        let mut case = TestCase::parse("$ todo sync", None, None).with_env("PATH", "/usr/bin");
+        case.bin_dir = Some("/tmp/stubs".into());
+
+        let envs = case.command_envs();
+        assert_eq!(envs.last(), Some(&("PATH".into(), "/tmp/stubs:/usr/bin".into())));
+    }
 }
@@ -1,25 +1,174 @@
-use std::fs;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::mem;
 use std::path::{Component, Path, PathBuf};
-use std::sync::LazyLock;
-
-use regex::Regex;
 
 use crate::error::{self, TestError};
+use crate::lexer::{Operator, Token};
 
-pub fn split_command_parts(command_line: &str) -> Vec<&str> {
-    static REGEX: LazyLock<Regex> =
-        LazyLock::new(|| Regex::new(r##"r?#"(?:.|\n)*"#|r?"(?:[^"]+)"|\S+"##).expect("regex must be correct"));
-
-    REGEX
-        .find_iter(command_line)
-        .map(|found| {
-            found
-                .as_str()
-                .trim_start_matches("r#\"")
-                .trim_matches('#')
-                .trim_matches('"')
-        })
-        .collect()
+/// Operator that joins pipelines into a command list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListOperator {
+    /// `&&`: run the next pipeline only if the previous one succeeded.
+    And,
+    /// `||`: run the next pipeline only if the previous one failed.
+    Or,
+    /// `;`: run the next pipeline unconditionally.
+    Sequence,
+}
+
+/// Split command tokens into pipelines joined by `&&`, `||`, `;` and `&`. Every pipeline is paired with the operator
+/// that precedes it, so the first one is always paired with `None`. A pipeline followed by `&` keeps the `&` as its
+/// last token and runs in the background, the next one is paired with `;`. Empty pipelines, e.g. after a trailing
+/// `;`, are skipped.
+pub fn split_command_list(tokens: &[Token]) -> Vec<(Option<ListOperator>, &[Token])> {
+    let mut list = Vec::new();
+    let mut operator = None;
+    let mut start = 0;
+
+    for (idx, token) in tokens.iter().enumerate() {
+        let next_operator = match token.operator() {
+            Some(Operator::And) => ListOperator::And,
+            Some(Operator::Or) => ListOperator::Or,
+            Some(Operator::Semicolon) => ListOperator::Sequence,
+            Some(Operator::Background) => {
+                list.push((operator, &tokens[start..=idx]));
+                operator = Some(ListOperator::Sequence);
+                start = idx + 1;
+                continue;
+            },
+            _ => continue,
+        };
+
+        if start < idx {
+            list.push((operator, &tokens[start..idx]));
+        }
+        operator = Some(next_operator);
+        start = idx + 1;
+    }
+
+    if start < tokens.len() {
+        list.push((operator, &tokens[start..]));
+    }
+    list
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteMode {
+    Truncate,
+    Append,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StderrTarget {
+    /// The unredirected stdout of the command, e.g. of `2>&1 > file`.
+    Stdout,
+    File(PathBuf, WriteMode),
+}
+
+/// Redirections of the command output streams: `>`, `>>`, `2>`, `2>>`, `2>&1`, `&>` and `&>>`. They are applied from
+/// left to right like in a POSIX shell, so `2>&1` redirects stderr to the target of stdout at that point.
+#[derive(Debug, Default)]
+pub struct Redirects {
+    pub stdout: Option<(PathBuf, WriteMode)>,
+    pub stderr: Option<StderrTarget>,
+}
+
+impl Redirects {
+    /// Split command tokens into the command arguments and the output redirections. Target files are resolved
+    /// relative to `current_dir` and must be inside `test_dir`.
+    pub fn split(
+        test_dir: impl AsRef<Path>,
+        current_dir: impl AsRef<Path>,
+        tokens: &[Token],
+    ) -> error::Result<(Vec<&str>, Self)> {
+        let (test_dir, current_dir) = (test_dir.as_ref(), current_dir.as_ref());
+        let mut args = Vec::new();
+        let mut redirects = Self::default();
+        let mut tokens = tokens.iter();
+
+        while let Some(token) = tokens.next() {
+            let (stdout, stderr, mode) = match token.operator() {
+                None => {
+                    args.extend(token.word());
+                    continue;
+                },
+                Some(Operator::StderrToStdout) => {
+                    redirects.stderr = Some(match &redirects.stdout {
+                        Some((path, mode)) => StderrTarget::File(path.clone(), *mode),
+                        None => StderrTarget::Stdout,
+                    });
+                    continue;
+                },
+                Some(Operator::RedirectStdout(mode)) => (true, false, mode),
+                Some(Operator::RedirectStderr(mode)) => (false, true, mode),
+                Some(Operator::RedirectAll(mode)) => (true, true, mode),
+                Some(operator) => {
+                    return Err(TestError::Command(format!("Unexpected operator {operator:?}")));
+                },
+            };
+
+            let Some(path) = tokens.next().and_then(Token::word) else {
+                return Err(TestError::Command("Missing redirection target file".into()));
+            };
+            let path = checked_join(test_dir, current_dir, path)?;
+
+            if stdout {
+                redirects.stdout = Some((path.clone(), mode));
+            }
+            if stderr {
+                redirects.stderr = Some(StderrTarget::File(path, mode));
+            }
+        }
+
+        Ok((args, redirects))
+    }
+
+    pub fn is_to_file(&self) -> bool {
+        self.stdout.is_some() || matches!(self.stderr, Some(StderrTarget::File(..)))
+    }
+
+    /// Write the redirected streams to their files and return the output left for the terminal.
+    /// Returns `None` if some stream was written to a file and nothing is left.
+    pub fn apply(&self, stdout: impl Into<String>, stderr: impl Into<String>) -> error::Result<Option<String>> {
+        let (stdout, stderr) = self.apply_streams(stdout, stderr)?;
+        let output = format!("{stdout}{stderr}");
+
+        if self.is_to_file() && output.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(output))
+        }
+    }
+
+    /// Write the redirected streams to their files and return the stdout and stderr left unredirected.
+    pub fn apply_streams(
+        &self,
+        stdout: impl Into<String>,
+        stderr: impl Into<String>,
+    ) -> error::Result<(String, String)> {
+        let (mut stdout, mut stderr) = (stdout.into(), stderr.into());
+
+        match &self.stderr {
+            // Both streams redirected to the same file are written at once, stdout first
+            Some(StderrTarget::File(path, _))
+                if self.stdout.as_ref().is_some_and(|(stdout_path, _)| stdout_path == path) =>
+            {
+                stdout.push_str(&mem::take(&mut stderr));
+            },
+            Some(StderrTarget::File(path, mode)) => write_file(path, &mem::take(&mut stderr), *mode)?,
+            Some(StderrTarget::Stdout) | None => {},
+        }
+
+        if let Some((path, mode)) = &self.stdout {
+            write_file(path, &mem::take(&mut stdout), *mode)?;
+        }
+        if self.stderr == Some(StderrTarget::Stdout) {
+            stdout.push_str(&mem::take(&mut stderr));
+        }
+
+        Ok((stdout, stderr))
+    }
 }
 
 #[derive(Debug)]
@@ -28,8 +177,8 @@ pub enum Cmd {
     Ls(PathBuf),
     Mkdir(Vec<PathBuf>),
     Rm(Vec<PathBuf>),
-    Echo(String, Option<PathBuf>),
-    Cat(PathBuf, Option<PathBuf>),
+    Echo(String),
+    Cat(Option<PathBuf>),
 }
 
 pub enum CmdResponse {
@@ -39,46 +188,92 @@ pub enum CmdResponse {
 }
 
 impl Cmd {
-    pub fn parse(root_dir: impl AsRef<Path>, source: &str) -> Result<Self, Vec<&str>> {
-        let root_dir = root_dir.as_ref();
-        let parts = split_command_parts(source);
-
-        let cmd = match &parts[..] {
-            ["cd", path] => Self::Cd(checked_join(root_dir, path)),
-            ["ls", path] => Self::Ls(checked_join(root_dir, path)),
-            ["mkdir", pathes @ ..] => Self::Mkdir(pathes.iter().map(|path| checked_join(root_dir, path)).collect()),
-            ["rm", pathes @ ..] => Self::Rm(pathes.iter().map(|path| checked_join(root_dir, path)).collect()),
-            ["echo", text @ .., ">", path] => Self::Echo(text.to_vec().join(" "), Some(checked_join(root_dir, path))),
-            ["echo", text @ ..] => Self::Echo(text.to_vec().join(" "), None),
-            ["cat", from_path, ">", to_path] => {
-                Self::Cat(checked_join(root_dir, from_path), Some(checked_join(root_dir, to_path)))
-            },
-            ["cat", path] => Self::Cat(checked_join(root_dir, path), None),
-            _ => return Err(parts),
+    /// Parse a builtin command, returns `None` if the command is not a builtin one. Paths are resolved relative
+    /// to `current_dir` and must be inside `test_dir`.
+    pub fn parse(
+        test_dir: impl AsRef<Path>,
+        current_dir: impl AsRef<Path>,
+        parts: &[&str],
+    ) -> error::Result<Option<Self>> {
+        let (test_dir, current_dir) = (test_dir.as_ref(), current_dir.as_ref());
+        let join = |path: &&str| checked_join(test_dir, current_dir, path);
+
+        let cmd = match parts {
+            ["cd", path] => Self::Cd(join(path)?),
+            ["ls", path] => Self::Ls(join(path)?),
+            ["mkdir", pathes @ ..] => Self::Mkdir(pathes.iter().map(join).collect::<error::Result<_>>()?),
+            ["rm", pathes @ ..] => Self::Rm(pathes.iter().map(join).collect::<error::Result<_>>()?),
+            ["echo", text @ ..] => Self::Echo(text.to_vec().join(" ")),
+            ["cat"] => Self::Cat(None),
+            ["cat", path] => Self::Cat(Some(join(path)?)),
+            _ => return Ok(None),
         };
-        Ok(cmd)
+        Ok(Some(cmd))
     }
 
-    pub fn run(self) -> error::Result<CmdResponse> {
+    /// Run the command, `stdin` is passed to it when the command is a stage of a pipeline.
+    pub fn run(self, stdin: Option<&str>) -> error::Result<CmdResponse> {
         match self {
             Self::Cd(path) => cd(path),
             Self::Ls(path) => ls(path),
             Self::Mkdir(pathes) => mkdir(pathes),
             Self::Rm(pathes) => rm(pathes),
-            Self::Echo(text, path) => echo(text, path),
-            Self::Cat(from, to) => cat(from, to),
+            Self::Echo(text) => Ok(CmdResponse::Output(text)),
+            Self::Cat(Some(path)) => cat(path),
+            Self::Cat(None) => Ok(CmdResponse::Output(stdin.unwrap_or_default().to_string())),
         }
     }
 }
 
-fn checked_join(root: impl AsRef<Path>, subpath: impl AsRef<Path>) -> PathBuf {
-    let root = root.as_ref();
-    let path = normalize_path(root.join(subpath));
+/// Join the argument to the current directory and check that the result does not leave the test directory,
+/// including through symlinks. The error leaves the command and its source empty, they are filled by the caller.
+pub(crate) fn checked_join(test_dir: &Path, current_dir: &Path, arg: &str) -> error::Result<PathBuf> {
+    let path = normalize_path(current_dir.join(arg));
 
-    if path.starts_with(root) {
-        path
+    let is_inside = path.starts_with(test_dir)
+        && match (resolve_symlinks(&path), resolve_symlinks(test_dir)) {
+            (Some(resolved_path), Some(resolved_test_dir)) => resolved_path.starts_with(resolved_test_dir),
+            _ => false,
+        };
+
+    if is_inside {
+        Ok(path)
     } else {
-        panic!("Path `{}` is not a subpath of `{}`", path.display(), root.display())
+        Err(TestError::SandboxEscape {
+            arg: arg.into(),
+            path,
+            command: String::new(),
+            source_path: String::new(),
+            source_line: 0,
+        })
+    }
+}
+
+/// Resolve symlinks in the existing part of the path, the rest of the path is appended as is. Returns `None` if
+/// the path goes through a dangling symlink.
+fn resolve_symlinks(path: &Path) -> Option<PathBuf> {
+    let mut existing = path;
+    let mut rest = Vec::new();
+
+    loop {
+        if let Ok(resolved) = existing.canonicalize() {
+            return Some(
+                rest.into_iter()
+                    .rev()
+                    .fold(resolved, |resolved, name| resolved.join(name)),
+            );
+        }
+        if existing.symlink_metadata().is_ok() {
+            return None;
+        }
+
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                rest.push(name);
+                existing = parent;
+            },
+            _ => return Some(path.to_path_buf()),
+        }
     }
 }
 
@@ -177,35 +372,140 @@ fn rm(pathes: Vec<PathBuf>) -> error::Result<CmdResponse> {
     Ok(CmdResponse::Success)
 }
 
-fn echo(text: String, path: Option<PathBuf>) -> error::Result<CmdResponse> {
-    if let Some(path) = path {
-        fs::write(&path, text)
-            .map_err(|err| TestError::Command(format!("Failed to write file `{}`: {err}", path.display())))?;
-        Ok(CmdResponse::Success)
-    } else {
-        Ok(CmdResponse::Output(text))
-    }
+fn cat(path: PathBuf) -> error::Result<CmdResponse> {
+    let content = fs::read_to_string(&path)
+        .map_err(|err| TestError::Command(format!("Failed to read file `{}`: {err}", path.display())))?;
+    Ok(CmdResponse::Output(content))
 }
 
-fn cat(from_path: PathBuf, to_path: Option<PathBuf>) -> error::Result<CmdResponse> {
-    let content = fs::read_to_string(&from_path)
-        .map_err(|err| TestError::Command(format!("Failed to read file `{}`: {err}", from_path.display())))?;
-    echo(content, to_path)
+fn write_file(path: &Path, content: &str, mode: WriteMode) -> error::Result<()> {
+    let mut file = match mode {
+        WriteMode::Truncate => OpenOptions::new().write(true).create(true).truncate(true).open(path),
+        WriteMode::Append => OpenOptions::new().append(true).create(true).open(path),
+    }
+    .map_err(|err| TestError::Command(format!("Failed to open file `{}`: {err}", path.display())))?;
+
+    file.write_all(content.as_bytes())
+        .map_err(|err| TestError::Command(format!("Failed to write file `{}`: {err}", path.display())))
 }
 
 #[cfg(test)]
 mod tests {
-    use super::split_command_parts;
+    use std::fs;
+    use std::path::Path;
+
+    use temp_testdir::TempDir;
+
+    use super::{ListOperator, Redirects, StderrTarget, WriteMode, split_command_list};
+    use crate::error;
+    use crate::lexer::{Operator, Token, tokenize};
+
+    fn words(tokens: &[Token]) -> Vec<&str> {
+        tokens.iter().filter_map(Token::word).collect()
+    }
+
+    fn split_redirects(root: impl AsRef<Path>, source: &str) -> error::Result<(Vec<String>, Redirects)> {
+        let tokens = tokenize(source)?;
+        let (args, redirects) = Redirects::split(&root, &root, &tokens)?;
+        Ok((args.into_iter().map(Into::into).collect(), redirects))
+    }
 
     #[test]
-    fn split_command() {
-        assert_eq!(split_command_parts("mkdir a b c"), vec!["mkdir", "a", "b", "c"]);
-        assert_eq!(split_command_parts("cd a/b cd \"ef g\""), vec![
-            "cd", "a/b", "cd", "ef g"
-        ]);
-        assert_eq!(split_command_parts("echo \"test A\""), vec!["echo", "test A"]);
-        assert_eq!(split_command_parts("echo a \"b c d\" ef"), vec![
-            "echo", "a", "b c d", "ef"
+    fn split_list() {
+        let tokens = tokenize("mkdir a && cd a || echo failed; ls . ;").unwrap();
+        let list = split_command_list(&tokens)
+            .into_iter()
+            .map(|(operator, tokens)| (operator, words(tokens)))
+            .collect::<Vec<_>>();
+        assert_eq!(list, vec![
+            (None, vec!["mkdir", "a"]),
+            (Some(ListOperator::And), vec!["cd", "a"]),
+            (Some(ListOperator::Or), vec!["echo", "failed"]),
+            (Some(ListOperator::Sequence), vec!["ls", "."]),
         ]);
+
+        let tokens = tokenize("todod serve & todo list").unwrap();
+        let list = split_command_list(&tokens);
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].1.last().and_then(Token::operator), Some(Operator::Background));
+        assert_eq!(list[1].0, Some(ListOperator::Sequence));
+        assert_eq!(words(list[1].1), vec!["todo", "list"]);
+
+        let tokens = tokenize("echo yes | todo reset").unwrap();
+        let list = split_command_list(&tokens);
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].0, None);
+        assert_eq!(list[0].1.len(), 5);
+    }
+
+    #[test]
+    fn split_command_redirects() {
+        let root = TempDir::default();
+
+        let (args, redirects) = split_redirects(&root, "todo export > tasks.json").unwrap();
+        assert_eq!(args, vec!["todo", "export"]);
+        assert_eq!(redirects.stdout, Some((root.join("tasks.json"), WriteMode::Truncate)));
+        assert!(redirects.stderr.is_none());
+
+        let (args, redirects) = split_redirects(&root, "todo list >> log 2>&1").unwrap();
+        assert_eq!(args, vec!["todo", "list"]);
+        assert_eq!(redirects.stdout, Some((root.join("log"), WriteMode::Append)));
+        assert_eq!(
+            redirects.stderr,
+            Some(StderrTarget::File(root.join("log"), WriteMode::Append))
+        );
+
+        let (_, redirects) = split_redirects(&root, "todo list 2>&1 >> log").unwrap();
+        assert_eq!(redirects.stdout, Some((root.join("log"), WriteMode::Append)));
+        assert_eq!(redirects.stderr, Some(StderrTarget::Stdout));
+
+        let (args, redirects) = split_redirects(&root, "todo 2>> err &> all").unwrap();
+        assert_eq!(args, vec!["todo"]);
+        assert_eq!(redirects.stdout, Some((root.join("all"), WriteMode::Truncate)));
+        assert_eq!(
+            redirects.stderr,
+            Some(StderrTarget::File(root.join("all"), WriteMode::Truncate))
+        );
+
+        let (args, redirects) = split_redirects(&root, "echo \">\" 2>&1").unwrap();
+        assert_eq!(args, vec!["echo", ">"]);
+        assert!(matches!(redirects.stderr, Some(StderrTarget::Stdout)));
+
+        assert!(split_redirects(&root, "echo >").is_err());
+        assert!(split_redirects(&root, "echo > &").is_err());
+    }
+
+    #[test]
+    fn apply_redirects() {
+        let root = TempDir::default();
+
+        let (_, redirects) = split_redirects(&root, "> out").unwrap();
+        assert_eq!(redirects.apply("a\n", "").unwrap(), None);
+        assert_eq!(redirects.apply("b\n", "error\n").unwrap().as_deref(), Some("error\n"));
+        assert_eq!(fs::read_to_string(root.join("out")).unwrap(), "b\n");
+
+        let (_, redirects) = split_redirects(&root, ">> out 2>> err").unwrap();
+        assert_eq!(redirects.apply("c\n", "error\n").unwrap(), None);
+        assert_eq!(fs::read_to_string(root.join("out")).unwrap(), "b\nc\n");
+        assert_eq!(fs::read_to_string(root.join("err")).unwrap(), "error\n");
+
+        let (_, redirects) = split_redirects(&root, "2>&1").unwrap();
+        assert_eq!(
+            redirects.apply("d\n", "error\n").unwrap().as_deref(),
+            Some("d\nerror\n")
+        );
+
+        let (_, redirects) = split_redirects(&root, "&>> out").unwrap();
+        assert_eq!(redirects.apply("e\n", "error\n").unwrap(), None);
+        assert_eq!(fs::read_to_string(root.join("out")).unwrap(), "b\nc\ne\nerror\n");
+
+        // The redirections are applied from left to right
+        let (_, redirects) = split_redirects(&root, "> both 2>&1").unwrap();
+        assert_eq!(redirects.apply("f\n", "error\n").unwrap(), None);
+        assert_eq!(fs::read_to_string(root.join("both")).unwrap(), "f\nerror\n");
+
+        let (_, redirects) = split_redirects(&root, "2>&1 > only-stdout").unwrap();
+        assert_eq!(redirects.apply("g\n", "error\n").unwrap().as_deref(), Some("error\n"));
+        assert_eq!(fs::read_to_string(root.join("only-stdout")).unwrap(), "g\n");
     }
 }
@@ -0,0 +1,159 @@
+use std::path::Path;
+use std::{fs, io};
+
+use crate::lexer::shell_quote;
+
+/// File of the stub bin directory where the stubs log their invocations, one line per invocation.
+pub const STUB_LOG_FILE: &str = "stub.log";
+
+/// Executable placed on the `PATH` of the tested binary instead of a real tool, e.g. `git` or `editor`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandStub {
+    pub name: String,
+    pub action: StubAction,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StubAction {
+    /// Script run with the arguments of the stub, a shell script unless it starts with a shebang line.
+    Script(String),
+    /// Canned output printed to stdout before exiting with the code.
+    Output { stdout: String, exit_code: i32 },
+}
+
+impl CommandStub {
+    pub fn script(name: impl Into<String>, script: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            action: StubAction::Script(script.into()),
+        }
+    }
+
+    pub fn output(name: impl Into<String>, stdout: impl Into<String>, exit_code: i32) -> Self {
+        Self {
+            name: name.into(),
+            action: StubAction::Output {
+                stdout: stdout.into(),
+                exit_code,
+            },
+        }
+    }
+
+    /// Write the executable of the stub into the directory. It appends its name and arguments to the
+    /// [`STUB_LOG_FILE`] of the directory, then runs the script or prints the output.
+    pub fn install(&self, bin_dir: &Path) -> io::Result<()> {
+        if self.name.is_empty() || self.name.contains(['/', '\\']) || self.name.starts_with('.') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid stub command name `{}`", self.name),
+            ));
+        }
+
+        // The arguments are logged with the quoting rules of `shell_quote`, so that their boundaries are kept
+        let log_path = shell_quote(&bin_dir.join(STUB_LOG_FILE).to_string_lossy());
+        let mut wrapper = format!(
+            r#"#!/bin/sh
+quote() {{
+    case $1 in
+        '' | *[!A-Za-z0-9_./=:,@%+-]*) quoted=$(printf '%s.' "$1" | sed "s/'/'\\\\''/g"); printf " '%s'" "${{quoted%.}}" ;;
+        *) printf ' %s' "$1" ;;
+    esac
+}}
+{{ printf '%s' {name}; for arg in "$@"; do quote "$arg"; done; echo; }} >> {log_path}
+"#,
+            name = shell_quote(&self.name)
+        );
+
+        // The stub content is kept in a hidden file next to the wrapper
+        let content_path = bin_dir.join(format!(".{}", self.name));
+        match &self.action {
+            StubAction::Script(script) => {
+                let script = if script.starts_with("#!") {
+                    script.clone()
+                } else {
+                    format!("#!/bin/sh\n{script}")
+                };
+                write_executable(&content_path, &script)?;
+                wrapper.push_str(&format!(
+                    "exec {} \"$@\"\n",
+                    shell_quote(&content_path.to_string_lossy())
+                ));
+            },
+            StubAction::Output { stdout, exit_code } => {
+                fs::write(&content_path, stdout)?;
+                wrapper.push_str(&format!(
+                    "cat {}\nexit {exit_code}\n",
+                    shell_quote(&content_path.to_string_lossy())
+                ));
+            },
+        }
+        write_executable(&bin_dir.join(&self.name), &wrapper)
+    }
+}
+
+/// Read the logged invocations of the stubs with the names, of all stubs if there are no names.
+pub fn read_log(bin_dir: &Path, names: &[&str]) -> io::Result<String> {
+    let log = match fs::read_to_string(bin_dir.join(STUB_LOG_FILE)) {
+        Ok(log) => log,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(String::new()),
+        Err(err) => return Err(err),
+    };
+
+    Ok(log
+        .split_inclusive('\n')
+        .filter(|line| {
+            let name = line.split([' ', '\n']).next().unwrap_or_default();
+            names.is_empty() || names.contains(&name)
+        })
+        .collect())
+}
+
+fn write_executable(path: &Path, content: &str) -> io::Result<()> {
+    fs::write(path, content)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::process::Command;
+
+    use temp_testdir::TempDir;
+
+    use super::{CommandStub, read_log};
+
+    #[test]
+    fn run_stubs() {
+        let bin_dir = TempDir::default();
+        CommandStub::script("git", "echo \"git $1\"").install(&bin_dir).unwrap();
+        CommandStub::output("editor", "saved\n", 3).install(&bin_dir).unwrap();
+        assert!(CommandStub::output("../sh", "", 0).install(&bin_dir).is_err());
+
+        let output = Command::new(bin_dir.join("git"))
+            .args(["commit", "-m", "a b"])
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "git commit\n");
+
+        let output = Command::new(bin_dir.join("editor")).output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "saved\n");
+        assert_eq!(output.status.code(), Some(3));
+
+        assert_eq!(read_log(&bin_dir, &[]).unwrap(), "git commit -m 'a b'\neditor\n");
+        Command::new(bin_dir.join("git"))
+            .args(["tag", "", "it's"])
+            .output()
+            .unwrap();
+        assert_eq!(
+            read_log(&bin_dir, &["git"]).unwrap(),
+            "git commit -m 'a b'\ngit tag '' 'it'\\''s'\n"
+        );
+        assert_eq!(read_log(&bin_dir, &["editor"]).unwrap(), "editor\n");
+    }
+}
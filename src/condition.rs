@@ -0,0 +1,275 @@
+use std::env;
+use std::fmt::{self, Display, Formatter};
+
+/// Condition of a code block, the block is skipped if it is not met.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    /// `cfg(unix)`, `cfg(target_os = "linux")`, `cfg(not(windows))`...
+    Cfg(CfgExpr),
+    /// `feature=sqlite`, the feature must be enabled with `Tester::with_feature`.
+    Feature(String),
+    /// `env=CI`, the environment variable must be set and not empty.
+    Env(String),
+}
+
+impl Condition {
+    /// Parse the condition attribute of a block info string or a directive, `None` if it is not a condition.
+    pub fn parse(attr: &str) -> Option<Result<Self, String>> {
+        if attr.starts_with("cfg(") {
+            return Some(CfgExpr::parse(attr).and_then(|expr| match expr {
+                CfgExpr::List(ListOp::Cfg, mut exprs) if exprs.len() == 1 => Ok(Self::Cfg(exprs.remove(0))),
+                _ => Err(format!("invalid condition `{attr}`, expected a single predicate")),
+            }));
+        }
+
+        let (key, value) = attr.split_once('=')?;
+        let condition = match key {
+            "feature" => Self::Feature,
+            "env" => Self::Env,
+            _ => return None,
+        };
+        Some(if value.is_empty() {
+            Err(format!("empty `{key}` condition"))
+        } else {
+            Ok(condition(value.into()))
+        })
+    }
+
+    pub fn is_met(&self, features: &[String]) -> bool {
+        match self {
+            Self::Cfg(expr) => expr.is_met(features),
+            Self::Feature(feature) => features.contains(feature),
+            Self::Env(name) => env::var_os(name).is_some_and(|val| !val.is_empty()),
+        }
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cfg(expr) => write!(f, "cfg({expr})"),
+            Self::Feature(feature) => write!(f, "feature={feature}"),
+            Self::Env(name) => write!(f, "env={name}"),
+        }
+    }
+}
+
+/// Predicate of a `cfg(...)` condition, evaluated for the platform the tests run on.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CfgExpr {
+    /// `unix` or `windows`.
+    Name(String),
+    /// `target_os = "linux"`, `target_family = "unix"`, `target_arch = "x86_64"` or `feature = "sqlite"`.
+    KeyValue(String, String),
+    List(ListOp, Vec<CfgExpr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListOp {
+    /// The outer `cfg(...)` holding a single predicate.
+    Cfg,
+    Any,
+    All,
+    Not,
+}
+
+impl CfgExpr {
+    fn parse(source: &str) -> Result<Self, String> {
+        let mut parser = CfgParser { source, pos: 0 };
+        let expr = parser.expr()?;
+        parser.skip_whitespace();
+        if parser.pos < source.len() {
+            return Err(parser.error("unexpected input"));
+        }
+        Ok(expr)
+    }
+
+    fn is_met(&self, features: &[String]) -> bool {
+        match self {
+            Self::Name(name) => match name.as_str() {
+                "unix" | "windows" => env::consts::FAMILY == name,
+                _ => false,
+            },
+            Self::KeyValue(key, value) => match key.as_str() {
+                "target_os" => env::consts::OS == value,
+                "target_family" => env::consts::FAMILY == value,
+                "target_arch" => env::consts::ARCH == value,
+                "feature" => features.contains(value),
+                _ => false,
+            },
+            Self::List(ListOp::Any, exprs) => exprs.iter().any(|expr| expr.is_met(features)),
+            Self::List(ListOp::All | ListOp::Cfg, exprs) => exprs.iter().all(|expr| expr.is_met(features)),
+            Self::List(ListOp::Not, exprs) => !exprs.iter().all(|expr| expr.is_met(features)),
+        }
+    }
+}
+
+impl Display for CfgExpr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Name(name) => f.write_str(name),
+            Self::KeyValue(key, value) => write!(f, "{key} = \"{value}\""),
+            Self::List(op, exprs) => {
+                let name = match op {
+                    ListOp::Cfg => "cfg",
+                    ListOp::Any => "any",
+                    ListOp::All => "all",
+                    ListOp::Not => "not",
+                };
+                write!(f, "{name}(")?;
+                for (idx, expr) in exprs.iter().enumerate() {
+                    if idx > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{expr}")?;
+                }
+                f.write_str(")")
+            },
+        }
+    }
+}
+
+struct CfgParser<'a> {
+    source: &'a str,
+    pos: usize,
+}
+
+impl<'a> CfgParser<'a> {
+    fn expr(&mut self) -> Result<CfgExpr, String> {
+        let ident = self.ident()?;
+        self.skip_whitespace();
+
+        let op = match ident {
+            "cfg" => ListOp::Cfg,
+            "any" => ListOp::Any,
+            "all" => ListOp::All,
+            "not" => ListOp::Not,
+            _ if self.eat('=') => {
+                self.skip_whitespace();
+                return Ok(CfgExpr::KeyValue(ident.into(), self.string()?));
+            },
+            _ => return Ok(CfgExpr::Name(ident.into())),
+        };
+
+        if !self.eat('(') {
+            return Err(self.error("expected `(`"));
+        }
+        let mut exprs = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.eat(')') {
+                break;
+            }
+            exprs.push(self.expr()?);
+            self.skip_whitespace();
+            if !self.eat(',') {
+                if self.eat(')') {
+                    break;
+                }
+                return Err(self.error("expected `,` or `)`"));
+            }
+        }
+
+        if matches!(op, ListOp::Cfg | ListOp::Not) && exprs.len() != 1 {
+            return Err(self.error(&format!("`{ident}` expects a single predicate")));
+        }
+        Ok(CfgExpr::List(op, exprs))
+    }
+
+    fn ident(&mut self) -> Result<&'a str, String> {
+        let rest: &'a str = &self.source[self.pos..];
+        let len = rest
+            .find(|ch: char| !(ch.is_alphanumeric() || ch == '_'))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.error("expected an identifier"));
+        }
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if !self.eat('"') {
+            return Err(self.error("expected a string"));
+        }
+        let rest = &self.source[self.pos..];
+        let len = rest.find('"').ok_or_else(|| self.error("unterminated string"))?;
+        self.pos += len + 1;
+        Ok(rest[..len].into())
+    }
+
+    fn eat(&mut self, ch: char) -> bool {
+        let is_next = self.source[self.pos..].starts_with(ch);
+        if is_next {
+            self.pos += ch.len_utf8();
+        }
+        is_next
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.source[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("{message} at position {} of `{}`", self.pos, self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::Condition;
+
+    fn parse(attr: &str) -> Condition {
+        Condition::parse(attr).unwrap().unwrap()
+    }
+
+    #[test]
+    fn parse_conditions() {
+        assert_eq!(Condition::parse("setup"), None);
+        assert_eq!(Condition::parse("file=a.toml"), None);
+        assert_eq!(parse("feature=sqlite"), Condition::Feature("sqlite".into()));
+        assert_eq!(parse("env=CI"), Condition::Env("CI".into()));
+
+        for (attr, display) in [
+            ("cfg(unix)", "cfg(unix)"),
+            ("cfg(target_os=\"linux\")", "cfg(target_os = \"linux\")"),
+            (
+                "cfg(all( unix , not(target_os = \"macos\"), any()))",
+                "cfg(all(unix, not(target_os = \"macos\"), any()))",
+            ),
+        ] {
+            assert_eq!(parse(attr).to_string(), display);
+        }
+
+        for invalid in [
+            "cfg()",
+            "cfg(unix, windows)",
+            "cfg(not(unix, windows))",
+            "cfg(unix",
+            "cfg(target_os = linux)",
+            "cfg(unix) x",
+            "feature=",
+        ] {
+            assert!(matches!(Condition::parse(invalid), Some(Err(_))), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn evaluate_conditions() {
+        let features = ["sqlite".to_string()];
+        let os = format!("cfg(target_os = \"{}\")", env::consts::OS);
+
+        assert!(parse(&os).is_met(&features));
+        assert!(parse("cfg(any(unix, windows))").is_met(&features));
+        assert!(!parse("cfg(all(unix, windows))").is_met(&features));
+        assert!(parse("cfg(not(target_os = \"unknown\"))").is_met(&features));
+        assert!(parse("cfg(feature = \"sqlite\")").is_met(&features));
+        assert!(parse("feature=sqlite").is_met(&features));
+        assert!(!parse("feature=postgres").is_met(&features));
+        assert!(parse("env=CARGO_PKG_NAME").is_met(&features));
+        assert!(!parse("env=MD_CLI_TEST_UNSET_VAR").is_met(&features));
+    }
+}
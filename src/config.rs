@@ -0,0 +1,261 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+use serde::Deserialize;
+
+use crate::ansi::AnsiMode;
+use crate::normalize;
+
+/// Name of the project config file at the crate root.
+pub const PROJECT_CONFIG_FILE: &str = "md-cli-test.toml";
+
+/// Settings of a markdown file declared in its front matter, a YAML block delimited by `---` lines or a TOML block
+/// delimited by `+++` lines at the top of the file:
+///
+/// ```md
+/// ---
+/// cargo-bin-alias: todo
+/// env:
+///   TODO_CONFIG: ./todo.toml
+/// languages: [sh, console]
+/// ---
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct FileConfig {
+    pub cargo_bin_alias: Option<String>,
+    pub cargo_bin_name: Option<String>,
+    pub env: BTreeMap<String, String>,
+    /// Languages of the code blocks with commands, `sh` and `shell` by default.
+    pub languages: Vec<String>,
+}
+
+/// Settings of all markdown files of a crate declared in the `md-cli-test.toml` file at the crate root:
+///
+/// ```toml
+/// cargo-bin-alias = "todo"
+/// cargo-bin-name = "todo-cli"
+/// fixture-dirs = ["tests/fixtures/base"]
+/// timeout = 10
+/// ansi = "strip"
+/// normalize = ["timestamp", "temp-dir"]
+///
+/// [env]
+/// TODO_CONFIG = "./todo.toml"
+///
+/// [[replace]]
+/// from = "todo-cli.exe"
+/// to = "todo-cli"
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ProjectConfig {
+    pub cargo_bin_alias: Option<String>,
+    pub cargo_bin_name: Option<String>,
+    pub env: BTreeMap<String, String>,
+    /// Languages of the code blocks with commands, `sh` and `shell` by default.
+    pub languages: Vec<String>,
+    /// Fixture directories relative to the crate root.
+    pub fixture_dirs: Vec<PathBuf>,
+    /// Maximum duration of each command in seconds.
+    pub timeout: Option<f64>,
+    /// Replacements applied to the output of the commands before it is compared to the expected output.
+    pub replace: Vec<Replacement>,
+    /// Handling of the ANSI escape sequences in the output: `keep`, `strip` or `markup`.
+    pub ansi: Option<AnsiMode>,
+    /// Names of the built-in normalizers, see [`normalize::builtin`], applied before the replacements.
+    pub normalize: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Replacement {
+    pub from: String,
+    pub to: String,
+}
+
+impl ProjectConfig {
+    /// Load the `md-cli-test.toml` file from the crate root directory, `None` if there is no such file.
+    pub fn load(crate_dir: impl AsRef<Path>) -> Result<Option<Self>, String> {
+        let path = crate_dir.as_ref().join(PROJECT_CONFIG_FILE);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.to_string()),
+        };
+        let config: Self = toml::from_str(&content).map_err(|err| err.to_string())?;
+
+        if let Some(timeout) = config.timeout
+            && !(timeout.is_finite() && timeout > 0.0)
+        {
+            return Err(format!(
+                "invalid timeout `{timeout}`, expected a positive number of seconds"
+            ));
+        }
+        if let Some(name) = config.normalize.iter().find(|name| normalize::builtin(name).is_none()) {
+            return Err(format!("unknown normalizer `{name}`"));
+        }
+        Ok(Some(config))
+    }
+}
+
+/// Which settings win when both the `Tester` builder and the markdown file set them.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ConfigPrecedence {
+    #[default]
+    Builder,
+    File,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrontMatterFormat {
+    Yaml,
+    Toml,
+}
+
+/// Front matter block at the top of a markdown file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrontMatter<'a> {
+    pub format: FrontMatterFormat,
+    /// Content between the delimiter lines.
+    pub text: &'a str,
+    /// Offset of the markdown following the block.
+    pub end: usize,
+}
+
+impl FrontMatter<'_> {
+    pub fn parse_config(&self) -> Result<FileConfig, String> {
+        match self.format {
+            FrontMatterFormat::Yaml if self.text.trim().is_empty() => Ok(FileConfig::default()),
+            FrontMatterFormat::Yaml => serde_norway::from_str(self.text).map_err(|err| err.to_string()),
+            FrontMatterFormat::Toml => toml::from_str(self.text).map_err(|err| err.to_string()),
+        }
+    }
+}
+
+/// Find the front matter, `None` if the markdown does not start with a closed front matter block.
+pub fn front_matter(content: &str) -> Option<FrontMatter<'_>> {
+    let mut lines = content.split_inclusive('\n');
+    let first_line = lines.next()?;
+    let (format, delimiters): (_, &[&str]) = match first_line.trim_end() {
+        "---" => (FrontMatterFormat::Yaml, &["---", "..."]),
+        "+++" => (FrontMatterFormat::Toml, &["+++"]),
+        _ => return None,
+    };
+
+    let text_start = first_line.len();
+    let mut offset = text_start;
+    for line in lines {
+        if delimiters.contains(&line.trim_end()) {
+            return Some(FrontMatter {
+                format,
+                text: &content[text_start..offset],
+                end: offset + line.len(),
+            });
+        }
+        offset += line.len();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use temp_testdir::TempDir;
+
+    use super::{FileConfig, FrontMatterFormat, PROJECT_CONFIG_FILE, ProjectConfig, Replacement, front_matter};
+
+    #[test]
+    fn parse_front_matter() {
+        assert_eq!(front_matter("# Title\n---\na: b\n---\n"), None);
+        assert_eq!(front_matter("---\nnot closed\n"), None);
+
+        let content = "---\ncargo-bin-alias: todo\nenv:\n  TODO_CONFIG: ./todo.toml\n---\n# Title\n";
+        let yaml = front_matter(content).unwrap();
+        assert_eq!(yaml.format, FrontMatterFormat::Yaml);
+        assert_eq!(&content[yaml.end..], "# Title\n");
+        assert_eq!(
+            yaml.parse_config(),
+            Ok(FileConfig {
+                cargo_bin_alias: Some("todo".into()),
+                env: [("TODO_CONFIG".into(), "./todo.toml".into())].into(),
+                ..Default::default()
+            })
+        );
+
+        let content = "+++\ncargo-bin-name = \"todo-cli\"\nlanguages = [\"console\"]\n+++";
+        let toml = front_matter(content).unwrap();
+        assert_eq!(toml.format, FrontMatterFormat::Toml);
+        assert_eq!(toml.end, content.len());
+        assert_eq!(
+            toml.parse_config(),
+            Ok(FileConfig {
+                cargo_bin_name: Some("todo-cli".into()),
+                languages: vec!["console".into()],
+                ..Default::default()
+            })
+        );
+
+        assert_eq!(
+            front_matter("---\n---\n").unwrap().parse_config(),
+            Ok(FileConfig::default())
+        );
+        assert!(front_matter("---\nunknown: 1\n---\n").unwrap().parse_config().is_err());
+    }
+
+    #[test]
+    fn load_project_config() {
+        let dir = TempDir::default();
+        assert_eq!(ProjectConfig::load(&dir), Ok(None));
+
+        fs::write(
+            dir.join(PROJECT_CONFIG_FILE),
+            r#"
+cargo-bin-alias = "todo"
+fixture-dirs = ["tests/fixtures/base"]
+timeout = 0.5
+
+[[replace]]
+from = "todo-cli.exe"
+to = "todo-cli"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            ProjectConfig::load(&dir),
+            Ok(Some(ProjectConfig {
+                cargo_bin_alias: Some("todo".into()),
+                fixture_dirs: vec!["tests/fixtures/base".into()],
+                timeout: Some(0.5),
+                replace: vec![Replacement {
+                    from: "todo-cli.exe".into(),
+                    to: "todo-cli".into(),
+                }],
+                ..Default::default()
+            }))
+        );
+
+        fs::write(dir.join(PROJECT_CONFIG_FILE), "timeout = -1\n").unwrap();
+        assert!(ProjectConfig::load(&dir).is_err());
+
+        fs::write(dir.join(PROJECT_CONFIG_FILE), "unknown = 1\n").unwrap();
+        assert!(ProjectConfig::load(&dir).is_err());
+    }
+
+    #[test]
+    fn load_readme_example() {
+        let readme = include_str!("../README.md");
+        let (_, section) = readme.split_once("## Project config").unwrap();
+        let (_, example) = section.split_once("```toml\n").unwrap();
+        let (example, _) = example.split_once("```").unwrap();
+
+        let dir = TempDir::default();
+        fs::write(dir.join(PROJECT_CONFIG_FILE), example).unwrap();
+        let config = ProjectConfig::load(&dir).unwrap().unwrap();
+        assert_eq!(config.normalize, ["timestamp", "uuid"]);
+        assert_eq!(config.env, [("TODO_CONFIG".into(), "./todo.toml".into())].into());
+        assert_eq!(config.replace.len(), 1);
+    }
+}
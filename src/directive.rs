@@ -0,0 +1,338 @@
+use std::time::Duration;
+
+use crate::ansi::AnsiMode;
+use crate::condition::Condition;
+use crate::job::Ready;
+use crate::pty::PtySize;
+
+/// Name of the HTML comment directives, e.g. `<!-- md-cli-test: timeout=5 -->`.
+pub const DIRECTIVE_NAME: &str = "md-cli-test";
+
+/// Test cases a directive applies to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    /// The next test case, `<!-- md-cli-test: ... -->`.
+    NextBlock,
+    /// The test cases of the current section, `<!-- md-cli-test(section): ... -->`.
+    Section,
+    /// All test cases of the markdown file, `<!-- md-cli-test(file): ... -->`.
+    File,
+}
+
+/// How the output of a command is compared to the expected output.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum MatchMode {
+    #[default]
+    Exact,
+    /// The output must contain the expected output.
+    Contains,
+    /// The output is not checked.
+    Ignore,
+}
+
+/// Options of a test case set by the directives, unset options fall back to the wider scopes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CaseOptions {
+    /// Environment variables passed to the commands, set with `env.NAME=value`.
+    pub envs: Vec<(String, String)>,
+    /// Maximum duration of each command, set with `timeout=<seconds>`.
+    pub timeout: Option<Duration>,
+    /// Directory, relative to the test directory, where the commands start, set with `cwd=<path>`.
+    pub working_dir: Option<String>,
+    /// Whether the test case is skipped, set with `skip` or `skip=<bool>`.
+    pub skip: Option<bool>,
+    /// Set with `match=exact|contains|ignore`.
+    pub match_mode: Option<MatchMode>,
+    /// Handling of the ANSI escape sequences in the output, set with `ansi=keep|strip|markup`.
+    pub ansi: Option<AnsiMode>,
+    /// Whether the tested binary runs in a pseudo-terminal, set with `tty`, `tty=<bool>` or `tty=<cols>x<rows>`.
+    pub tty: Option<bool>,
+    /// Window size of the pseudo-terminal, 80x24 by default.
+    pub tty_size: Option<PtySize>,
+    /// Condition for the background jobs of the test case to be ready, set with `ready=line:<text>`,
+    /// `ready=file:<path>` or `ready=port:<port>`.
+    pub ready: Option<Ready>,
+    /// Conditions to run the test case, set with `cfg(...)`, `feature=<name>` or `env=<NAME>`, or in the info string
+    /// of the code block. All of them must be met.
+    pub conditions: Vec<Condition>,
+}
+
+impl CaseOptions {
+    /// Set the option from a `key=value` directive argument.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        if let Some(condition) = Condition::parse(&format!("{key}={value}")) {
+            self.conditions.push(condition?);
+            return Ok(());
+        }
+
+        match key {
+            "timeout" => {
+                let secs = value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|secs| secs.is_finite() && *secs > 0.0)
+                    .ok_or_else(|| format!("invalid timeout `{value}`, expected a positive number of seconds"))?;
+                self.timeout = Some(Duration::from_secs_f64(secs));
+            },
+            "cwd" => self.working_dir = Some(value.into()),
+            "skip" => self.skip = Some(parse_bool(value)?),
+            "match" => {
+                self.match_mode = Some(match value {
+                    "exact" => MatchMode::Exact,
+                    "contains" => MatchMode::Contains,
+                    "ignore" => MatchMode::Ignore,
+                    _ => return Err(format!("unknown match mode `{value}`")),
+                })
+            },
+            "tty" => match PtySize::parse(value) {
+                Some(size) => {
+                    self.tty = Some(true);
+                    self.tty_size = Some(size);
+                },
+                None => {
+                    self.tty = Some(parse_bool(value).map_err(|_| format!("invalid terminal size `{value}`"))?);
+                },
+            },
+            "ready" => self.ready = Some(Ready::parse(value)?),
+            "ansi" => {
+                self.ansi = Some(AnsiMode::parse(value).ok_or_else(|| format!("unknown ANSI mode `{value}`"))?);
+            },
+            _ => match key.strip_prefix("env.") {
+                Some(name) if !name.is_empty() => self.envs.push((name.into(), value.into())),
+                _ => return Err(format!("unknown option `{key}`")),
+            },
+        }
+        Ok(())
+    }
+
+    /// Fill the unset options from the `defaults`. The default envs go first so the own envs override them.
+    pub fn or(mut self, defaults: &Self) -> Self {
+        self.envs.splice(0..0, defaults.envs.iter().cloned());
+        self.timeout = self.timeout.or(defaults.timeout);
+        self.working_dir = self.working_dir.or_else(|| defaults.working_dir.clone());
+        self.skip = self.skip.or(defaults.skip);
+        self.match_mode = self.match_mode.or(defaults.match_mode);
+        self.ansi = self.ansi.or(defaults.ansi);
+        self.tty = self.tty.or(defaults.tty);
+        self.tty_size = self.tty_size.or(defaults.tty_size);
+        self.ready = self.ready.or_else(|| defaults.ready.clone());
+        self.conditions.splice(0..0, defaults.conditions.iter().cloned());
+        self
+    }
+
+    /// Reason to skip the test case, if it is skipped by the `skip` directive or one of its conditions is not met.
+    pub fn skip_reason(&self, features: &[String]) -> Option<String> {
+        if self.skip.unwrap_or(false) {
+            return Some("skipped by directive".into());
+        }
+        self.conditions
+            .iter()
+            .find(|condition| !condition.is_met(features))
+            .map(|condition| format!("condition `{condition}` is not met"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Directive {
+    pub scope: Scope,
+    pub options: CaseOptions,
+}
+
+/// Parse the `<!-- md-cli-test: key=value ... -->` directive, `None` if the HTML is not a directive.
+pub fn parse_directive(html: &str) -> Option<Result<Directive, String>> {
+    let comment = html.trim().strip_prefix("<!--")?.strip_suffix("-->")?;
+    let body = comment.trim().strip_prefix(DIRECTIVE_NAME)?;
+    let (scope, args) = body.split_once(':')?;
+
+    let scope = match scope.trim() {
+        "" | "(block)" => Scope::NextBlock,
+        "(section)" => Scope::Section,
+        "(file)" => Scope::File,
+        scope => return Some(Err(format!("unknown scope `{scope}`"))),
+    };
+    Some(parse_options(args).map(|options| Directive { scope, options }))
+}
+
+fn parse_options(args: &str) -> Result<CaseOptions, String> {
+    let mut options = CaseOptions::default();
+
+    for arg in split_args(args)? {
+        if arg.starts_with("cfg(") {
+            options.conditions.extend(Condition::parse(&arg).transpose()?);
+            continue;
+        }
+        // A flag without value, e.g. `skip`, is enabled
+        let (key, value) = arg.split_once('=').unwrap_or((&arg, "true"));
+        options.set(key, value)?;
+    }
+    Ok(options)
+}
+
+/// Split the arguments at the whitespaces outside of quotes and parentheses, e.g. `cfg(target_os = "linux")`.
+/// The quotes are removed outside of parentheses and kept inside them for the condition parser.
+fn split_args(args: &str) -> Result<Vec<String>, String> {
+    let mut parts = Vec::new();
+    let mut part: Option<String> = None;
+    let mut depth = 0_usize;
+    let mut quote = None;
+    let mut chars = args.chars();
+
+    while let Some(ch) = chars.next() {
+        match (ch, quote) {
+            ('\\', Some('"')) if depth == 0 => {
+                let escaped = chars.next().ok_or("unterminated escape")?;
+                part.get_or_insert_default().push(escaped);
+            },
+            (ch, Some(open)) if ch == open => {
+                quote = None;
+                let part = part.get_or_insert_default();
+                if depth > 0 {
+                    part.push(ch);
+                }
+            },
+            (ch, Some(_)) => part.get_or_insert_default().push(ch),
+            ('"' | '\'', None) => {
+                quote = Some(ch);
+                let part = part.get_or_insert_default();
+                if depth > 0 {
+                    part.push(ch);
+                }
+            },
+            (ch, None) if ch.is_whitespace() && depth == 0 => parts.extend(part.take()),
+            (ch, None) => {
+                match ch {
+                    '(' => depth += 1,
+                    ')' => depth = depth.checked_sub(1).ok_or("unbalanced `)`")?,
+                    _ => {},
+                }
+                part.get_or_insert_default().push(ch);
+            },
+        }
+    }
+
+    if quote.is_some() {
+        return Err("unterminated quote".into());
+    }
+    if depth > 0 {
+        return Err("unbalanced `(`".into());
+    }
+    parts.extend(part);
+    Ok(parts)
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(format!("invalid boolean `{value}`")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{CaseOptions, Directive, MatchMode, Scope, parse_directive};
+    use crate::condition::Condition;
+    use crate::job::Ready;
+    use crate::pty::PtySize;
+
+    #[test]
+    fn parse_directives() {
+        assert_eq!(parse_directive("<!-- fixture: a -->"), None);
+        assert_eq!(parse_directive("<!-- md-cli-test is great -->"), None);
+
+        assert_eq!(
+            parse_directive("<!-- md-cli-test: env.NAME=\"a b\" timeout=0.5 skip -->\n"),
+            Some(Ok(Directive {
+                scope: Scope::NextBlock,
+                options: CaseOptions {
+                    envs: vec![("NAME".into(), "a b".into())],
+                    timeout: Some(Duration::from_millis(500)),
+                    skip: Some(true),
+                    ..Default::default()
+                },
+            }))
+        );
+        assert_eq!(
+            parse_directive("<!--md-cli-test(section): cwd=src match=contains-->"),
+            Some(Ok(Directive {
+                scope: Scope::Section,
+                options: CaseOptions {
+                    working_dir: Some("src".into()),
+                    match_mode: Some(MatchMode::Contains),
+                    ..Default::default()
+                },
+            }))
+        );
+        assert_eq!(
+            parse_directive("<!-- md-cli-test: tty=120x40 -->").map(|result| result.map(|dir| dir.options)),
+            Some(Ok(CaseOptions {
+                tty: Some(true),
+                tty_size: Some(PtySize { cols: 120, rows: 40 }),
+                ..Default::default()
+            }))
+        );
+        assert_eq!(
+            parse_directive("<!-- md-cli-test: ready=\"line:Listening on\" -->")
+                .map(|result| result.map(|dir| dir.options)),
+            Some(Ok(CaseOptions {
+                ready: Some(Ready::Line("Listening on".into())),
+                ..Default::default()
+            }))
+        );
+        let conditions = |html| parse_directive(html).map(|result| result.map(|dir| dir.options.conditions));
+        assert_eq!(
+            conditions("<!-- md-cli-test: cfg(target_os = \"linux\") -->"),
+            Some(Ok(vec![
+                Condition::parse("cfg(target_os = \"linux\")").unwrap().unwrap()
+            ]))
+        );
+        assert_eq!(
+            conditions("<!-- md-cli-test(section): cfg(any(unix, windows)) skip=false -->"),
+            Some(Ok(vec![Condition::parse("cfg(any(unix, windows))").unwrap().unwrap()]))
+        );
+        assert_eq!(
+            parse_directive("<!-- md-cli-test(file): skip=false -->").map(|result| result.map(|dir| dir.scope)),
+            Some(Ok(Scope::File))
+        );
+
+        for invalid in [
+            "<!-- md-cli-test(all): skip -->",
+            "<!-- md-cli-test: timeout=soon -->",
+            "<!-- md-cli-test: match=fuzzy -->",
+            "<!-- md-cli-test: ansi=colors -->",
+            "<!-- md-cli-test: tty=wide -->",
+            "<!-- md-cli-test: ready=port:http -->",
+            "<!-- md-cli-test: skip=yes -->",
+            "<!-- md-cli-test: unknown=1 -->",
+            "<!-- md-cli-test: skip | -->",
+            "<!-- md-cli-test: cfg(any(unix, windows) -->",
+            "<!-- md-cli-test: env.NAME=\"a b -->",
+        ] {
+            assert!(matches!(parse_directive(invalid), Some(Err(_))), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn merge_options() {
+        let file = CaseOptions {
+            envs: vec![("A".into(), "file".into())],
+            timeout: Some(Duration::from_secs(1)),
+            match_mode: Some(MatchMode::Ignore),
+            ..Default::default()
+        };
+        let block = CaseOptions {
+            envs: vec![("A".into(), "block".into())],
+            match_mode: Some(MatchMode::Exact),
+            ..Default::default()
+        };
+
+        assert_eq!(block.or(&file), CaseOptions {
+            envs: vec![("A".into(), "file".into()), ("A".into(), "block".into())],
+            timeout: Some(Duration::from_secs(1)),
+            match_mode: Some(MatchMode::Exact),
+            ..Default::default()
+        });
+    }
+}
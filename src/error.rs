@@ -1,7 +1,10 @@
+use std::path::PathBuf;
 use std::{env, io};
 
 use thiserror::Error;
 
+use crate::lexer::LexError;
+
 pub type Result<T> = std::result::Result<T, TestError>;
 
 #[derive(Debug, Error)]
@@ -9,6 +12,35 @@ pub enum TestError {
     #[error("IO error: {0}")]
     Io(#[from] io::Error),
 
+    #[error("Syntax error: {0}")]
+    Lex(#[from] LexError),
+
+    #[error(
+        "Argument `{arg}` leaves the test directory as `{}` in command `{command}` in source {source_path}:{source_line}",
+        path.display()
+    )]
+    SandboxEscape {
+        arg: String,
+        path: PathBuf,
+        command: String,
+        source_path: String,
+        source_line: usize,
+    },
+
+    #[error("Invalid config in `{}`: {reason}", path.display())]
+    Config { path: PathBuf, reason: String },
+
+    #[error("Invalid directive `{directive}` in source {source_path}:{source_line}: {reason}")]
+    Directive {
+        directive: String,
+        reason: String,
+        source_path: String,
+        source_line: usize,
+    },
+
+    #[error("{source}\nTest directory is kept at `{}`", dir.display())]
+    KeptDir { source: Box<TestError>, dir: PathBuf },
+
     #[error("Command IO error: {0}")]
     Command(String),
 
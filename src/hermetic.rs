@@ -0,0 +1,83 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+/// Environment variables that the binaries of a hermetic `Tester` inherit from the test process.
+pub const DEFAULT_ALLOWED_ENVS: [&str; 3] = ["PATH", "SYSTEMROOT", "LLVM_PROFILE_FILE"];
+
+/// Locale, time zone and build time of the binaries of a hermetic `Tester`.
+pub const FIXED_ENVS: [(&str, &str); 3] = [("LANG", "C.UTF-8"), ("TZ", "UTC"), ("SOURCE_DATE_EPOCH", "0")];
+
+/// Suffix of the directory next to the section test directory with the home and temporary directories of a
+/// hermetic `Tester`.
+pub const HERMETIC_DIR_SUFFIX: &str = "-hermetic";
+
+/// Path of the directory with the home and temporary directories of the section, e.g. `/tmp/rstest.1-hermetic` for
+/// the test directory `/tmp/rstest.1`. It is outside the test directory, so the binaries do not find it there.
+pub fn hermetic_dir(test_dir: &Path) -> PathBuf {
+    let mut name = test_dir.file_name().unwrap_or_default().to_os_string();
+    name.push(HERMETIC_DIR_SUFFIX);
+    test_dir.with_file_name(name)
+}
+
+/// Create an empty home and temporary directory in the directory and return the envs pointing the binaries to
+/// them, followed by the [`FIXED_ENVS`].
+pub fn isolated_envs(dir: &Path) -> io::Result<Vec<(OsString, OsString)>> {
+    let home = dir.join("home");
+    let tmp = dir.join("tmp");
+    fs::create_dir_all(&home)?;
+    fs::create_dir_all(&tmp)?;
+
+    let mut envs = vec![
+        ("HOME", home.clone()),
+        ("USERPROFILE", home.clone()),
+        ("XDG_CONFIG_HOME", home.join(".config")),
+        ("XDG_CACHE_HOME", home.join(".cache")),
+        ("XDG_DATA_HOME", home.join(".local").join("share")),
+        ("XDG_STATE_HOME", home.join(".local").join("state")),
+        ("APPDATA", home.join("AppData").join("Roaming")),
+        ("LOCALAPPDATA", home.join("AppData").join("Local")),
+        ("TMPDIR", tmp.clone()),
+        ("TEMP", tmp.clone()),
+        ("TMP", tmp),
+    ]
+    .into_iter()
+    .map(|(key, path)| (key.into(), path.into_os_string()))
+    .collect::<Vec<_>>();
+
+    envs.extend(FIXED_ENVS.iter().map(|(key, val)| (key.into(), val.into())));
+    Ok(envs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+    use std::path::Path;
+
+    use temp_testdir::TempDir;
+
+    use super::{hermetic_dir, isolated_envs};
+
+    #[test]
+    fn create_isolated_dirs() {
+        let dir = TempDir::default();
+        let envs = isolated_envs(&dir).unwrap();
+        let env = |key: &str| envs.iter().find(|(name, _)| name == key).map(|(_, val)| val.clone());
+
+        assert_eq!(env("HOME"), Some(dir.join("home").into_os_string()));
+        assert_eq!(
+            env("XDG_CONFIG_HOME"),
+            Some(dir.join("home").join(".config").into_os_string())
+        );
+        assert_eq!(env("TZ"), Some(OsString::from("UTC")));
+        assert!(dir.join("home").is_dir() && dir.join("tmp").is_dir());
+    }
+
+    #[test]
+    fn place_hermetic_dir_next_to_test_dir() {
+        assert_eq!(
+            hermetic_dir("/tmp/rstest.1".as_ref()),
+            Path::new("/tmp/rstest.1-hermetic")
+        );
+    }
+}
@@ -0,0 +1,379 @@
+use std::fmt::{self, Display, Formatter};
+use std::io::{BufRead, BufReader, Read};
+use std::net::{Ipv4Addr, TcpStream};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::cmd::checked_join;
+use crate::error::{self, TestError};
+
+/// Maximum time to wait for a background job to become ready unless a timeout is set.
+pub const DEFAULT_READY_TIMEOUT: Duration = Duration::from_secs(10);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Condition for a background job to be ready, the commands following it start only after it is met.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Ready {
+    /// `line:<text>`, the job printed a line containing the text.
+    Line(String),
+    /// `file:<path>`, the file exists, relative to the directory where the job started.
+    File(String),
+    /// `port:<port>`, the local TCP port accepts connections.
+    Port(u16),
+}
+
+impl Ready {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let ready = match value.split_once(':') {
+            Some(("line", text)) if !text.is_empty() => Self::Line(text.into()),
+            Some(("file", path)) if !path.is_empty() => Self::File(path.into()),
+            Some(("port", port)) => Self::Port(port.parse().map_err(|_| format!("invalid port `{port}`"))?),
+            _ => {
+                return Err(format!(
+                    "invalid ready condition `{value}`, expected `line:<text>`, `file:<path>` or `port:<port>`"
+                ));
+            },
+        };
+        Ok(ready)
+    }
+}
+
+impl Display for Ready {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Line(text) => write!(f, "line:{text}"),
+            Self::File(path) => write!(f, "file:{path}"),
+            Self::Port(port) => write!(f, "port:{port}"),
+        }
+    }
+}
+
+/// Builtin commands controlling the background jobs, `%N` selects the job `N`, all jobs are selected without
+/// arguments.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobCmd {
+    /// `wait [%N...]`: wait for the jobs to exit and print their output.
+    Wait(Vec<usize>),
+    /// `kill [%N...]`: kill the jobs.
+    Kill(Vec<usize>),
+}
+
+impl JobCmd {
+    /// Parse the builtin command, `None` if the command is not a job one.
+    pub fn parse(parts: &[&str]) -> Option<error::Result<Self>> {
+        let (cmd, specs): (fn(_) -> _, _) = match parts {
+            ["wait", specs @ ..] => (Self::Wait, specs),
+            ["kill", specs @ ..] => (Self::Kill, specs),
+            _ => return None,
+        };
+
+        let ids = specs
+            .iter()
+            .map(|spec| {
+                spec.strip_prefix('%')
+                    .and_then(|id| id.parse().ok())
+                    .ok_or_else(|| TestError::Command(format!("Invalid job `{spec}`, expected `%<number>`")))
+            })
+            .collect::<error::Result<_>>();
+        Some(ids.map(cmd))
+    }
+}
+
+/// Background jobs started by the `command &` lines of a section, shared by its test cases. The jobs that are still
+/// running are killed when the last handle is dropped.
+#[derive(Debug, Clone, Default)]
+pub struct Jobs(Arc<Mutex<JobTable>>);
+
+#[derive(Debug, Default)]
+struct JobTable {
+    jobs: Vec<Job>,
+    last_id: usize,
+}
+
+#[derive(Debug)]
+struct Job {
+    id: usize,
+    command: String,
+    child: Child,
+    /// Stdout and stderr lines of the job.
+    output: Arc<Mutex<String>>,
+    readers: Vec<JoinHandle<()>>,
+}
+
+impl Jobs {
+    /// Start the command in the background and return its job number.
+    pub fn start(&self, mut cmd: Command, command: &str) -> error::Result<usize> {
+        let mut child = cmd
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| TestError::Command(format!("Failed to start `{command}` in the background: {err}")))?;
+
+        let output = Arc::<Mutex<String>>::default();
+        let mut readers = Vec::new();
+        let streams: [Option<Box<dyn Read + Send>>; 2] = [
+            child.stdout.take().map(|stdout| Box::new(stdout) as _),
+            child.stderr.take().map(|stderr| Box::new(stderr) as _),
+        ];
+        for stream in streams.into_iter().flatten() {
+            let output = output.clone();
+            readers.push(thread::spawn(move || {
+                for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                    let mut output = output.lock().unwrap_or_else(|err| err.into_inner());
+                    output.push_str(&line);
+                    output.push('\n');
+                }
+            }));
+        }
+
+        let mut table = self.lock();
+        table.last_id += 1;
+        let id = table.last_id;
+        table.jobs.push(Job {
+            id,
+            command: command.into(),
+            child,
+            output,
+            readers,
+        });
+        Ok(id)
+    }
+
+    /// Wait until the job meets the condition, fail if it exits before or the timeout is reached. The file of the
+    /// condition is resolved relative to `current_dir` and must be inside `test_dir`.
+    pub fn wait_ready(
+        &self,
+        id: usize,
+        ready: &Ready,
+        test_dir: &Path,
+        current_dir: &Path,
+        timeout: Duration,
+    ) -> error::Result<()> {
+        let file = match ready {
+            Ready::File(path) => Some(checked_join(test_dir, current_dir, path)?),
+            Ready::Line(_) | Ready::Port(_) => None,
+        };
+        let deadline = Instant::now() + timeout;
+        loop {
+            {
+                let mut table = self.lock();
+                let job = table.get_mut(id)?;
+                let is_ready = match ready {
+                    Ready::Line(text) => job.output().lines().any(|line| line.contains(text.as_str())),
+                    Ready::File(_) => file.as_ref().is_some_and(|file| file.exists()),
+                    Ready::Port(port) => TcpStream::connect((Ipv4Addr::LOCALHOST, *port)).is_ok(),
+                };
+                if is_ready {
+                    return Ok(());
+                }
+
+                if let Ok(Some(status)) = job.child.try_wait() {
+                    return Err(TestError::Command(format!(
+                        "Background job `{}` exited with {status} before `ready={ready}`, its output:\n{}",
+                        job.command,
+                        job.output()
+                    )));
+                }
+                if Instant::now() >= deadline {
+                    return Err(TestError::Command(format!(
+                        "Background job `{}` is not ready with `ready={ready}` after {}s, its output:\n{}",
+                        job.command,
+                        timeout.as_secs_f64(),
+                        job.output()
+                    )));
+                }
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Run the `wait` or `kill` builtin and return its exit status and output.
+    pub fn run(&self, cmd: JobCmd, timeout: Option<Duration>) -> error::Result<(i32, String)> {
+        let mut table = self.lock();
+        let ids = match &cmd {
+            JobCmd::Wait(ids) | JobCmd::Kill(ids) if ids.is_empty() => table.jobs.iter().map(|job| job.id).collect(),
+            JobCmd::Wait(ids) | JobCmd::Kill(ids) => ids.clone(),
+        };
+
+        let mut status = 0;
+        let mut output = String::new();
+        for id in ids {
+            let mut job = table.remove(id)?;
+            match cmd {
+                JobCmd::Wait(_) => {
+                    status = job.wait(timeout)?;
+                    output.push_str(&job.output());
+                },
+                JobCmd::Kill(_) => job.kill(),
+            }
+        }
+        Ok((status, output))
+    }
+
+    /// Kill the jobs that are still running when the guard is dropped, e.g. at the end of a section.
+    pub fn kill_on_drop(&self) -> KillOnDrop {
+        KillOnDrop(self.clone())
+    }
+
+    /// Kill the jobs that are still running.
+    pub fn kill_all(&self) {
+        for mut job in self.lock().jobs.drain(..) {
+            log::debug!("Killing background job `{}`", job.command);
+            job.kill();
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, JobTable> {
+        // A test case may panic while holding the lock, the table stays consistent
+        self.0.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Guard returned by [`Jobs::kill_on_drop`].
+pub struct KillOnDrop(Jobs);
+
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        self.0.kill_all();
+    }
+}
+
+impl JobTable {
+    fn get_mut(&mut self, id: usize) -> error::Result<&mut Job> {
+        self.jobs
+            .iter_mut()
+            .find(|job| job.id == id)
+            .ok_or_else(|| TestError::Command(format!("No such job `%{id}`")))
+    }
+
+    fn remove(&mut self, id: usize) -> error::Result<Job> {
+        let idx = self
+            .jobs
+            .iter()
+            .position(|job| job.id == id)
+            .ok_or_else(|| TestError::Command(format!("No such job `%{id}`")))?;
+        Ok(self.jobs.remove(idx))
+    }
+}
+
+impl Drop for JobTable {
+    fn drop(&mut self) {
+        for job in &mut self.jobs {
+            job.kill();
+        }
+    }
+}
+
+impl Job {
+    fn output(&self) -> String {
+        self.output.lock().unwrap_or_else(|err| err.into_inner()).clone()
+    }
+
+    /// Wait for the job to exit and for its whole output.
+    fn wait(&mut self, timeout: Option<Duration>) -> error::Result<i32> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let status = loop {
+            if let Some(status) = self.child.try_wait()? {
+                break status;
+            }
+            if let (Some(deadline), Some(timeout)) = (deadline, timeout)
+                && Instant::now() >= deadline
+            {
+                self.kill();
+                return Err(TestError::Command(format!(
+                    "Background job `{}` timed out after {}s",
+                    self.command,
+                    timeout.as_secs_f64()
+                )));
+            }
+            thread::sleep(POLL_INTERVAL);
+        };
+
+        for reader in self.readers.drain(..) {
+            let _ = reader.join();
+        }
+        Ok(status.code().unwrap_or(-1))
+    }
+
+    fn kill(&mut self) {
+        if let Ok(None) = self.child.try_wait() {
+            let _ = self.child.kill();
+        }
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use temp_testdir::TempDir;
+
+    use super::{JobCmd, Jobs, Ready};
+    use crate::error::TestError;
+
+    #[test]
+    fn parse_ready_conditions() {
+        assert_eq!(
+            Ready::parse("line:Listening on"),
+            Ok(Ready::Line("Listening on".into()))
+        );
+        assert_eq!(Ready::parse("file:server.pid"), Ok(Ready::File("server.pid".into())));
+        assert_eq!(Ready::parse("port:8080"), Ok(Ready::Port(8080)));
+        for invalid in ["port:http", "line:", "8080", "socket:a"] {
+            assert!(Ready::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn parse_job_commands() {
+        assert!(JobCmd::parse(&["ls", "."]).is_none());
+        assert_eq!(JobCmd::parse(&["wait"]).unwrap().unwrap(), JobCmd::Wait(vec![]));
+        assert_eq!(
+            JobCmd::parse(&["kill", "%1", "%3"]).unwrap().unwrap(),
+            JobCmd::Kill(vec![1, 3])
+        );
+        assert!(JobCmd::parse(&["kill", "1234"]).unwrap().is_err());
+    }
+
+    #[test]
+    fn check_ready_file_inside_test_dir() {
+        let test_dir = TempDir::default();
+        let ready = Ready::File("../outside.pid".into());
+        let result = Jobs::default().wait_ready(1, &ready, &test_dir, &test_dir, Duration::from_millis(10));
+        assert!(matches!(result, Err(TestError::SandboxEscape { .. })), "{result:?}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_background_jobs() {
+        use std::process::Command;
+
+        let jobs = Jobs::default();
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "echo starting; sleep 0.1; echo ready; sleep 5"]);
+        let server = jobs.start(cmd, "server").unwrap();
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "echo done; exit 3"]);
+        let client = jobs.start(cmd, "client").unwrap();
+        assert_eq!((server, client), (1, 2));
+
+        let ready = Ready::Line("ready".into());
+        jobs.wait_ready(server, &ready, ".".as_ref(), ".".as_ref(), Duration::from_secs(5))
+            .unwrap();
+        assert_eq!(
+            jobs.run(JobCmd::Wait(vec![client]), None).unwrap(),
+            (3, "done\n".to_string())
+        );
+        assert!(jobs.run(JobCmd::Wait(vec![client]), None).is_err());
+
+        let timeout = Some(Duration::from_millis(100));
+        assert!(jobs.run(JobCmd::Wait(vec![server]), timeout).is_err());
+        assert!(jobs.lock().jobs.is_empty());
+    }
+}
@@ -0,0 +1,293 @@
+use std::fmt::Write as _;
+use std::path::{Component, Path};
+use std::{env, fs, io};
+
+use crate::report::{CommandReport, FileReport, Report, SectionReport, Status};
+
+/// Write the report as JUnit XML into the directory, a file per markdown file named after its path relative to
+/// the current directory, so that the testers of several files do not overwrite each other's reports.
+pub fn write_report(report: &Report, dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    for file in &report.files {
+        let file_report = Report {
+            files: vec![file.clone()],
+        };
+        fs::write(dir.join(report_name(&file.path)), to_xml(&file_report))?;
+    }
+    Ok(())
+}
+
+/// Name of the report of the markdown file, e.g. `docs-a-README.md.xml` for `docs/a/README.md`.
+fn report_name(path: &Path) -> String {
+    let relative = env::current_dir()
+        .ok()
+        .and_then(|current_dir| path.strip_prefix(current_dir).ok())
+        .unwrap_or(path);
+    let name = relative
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            Component::ParentDir => Some("..".into()),
+            _ => None,
+        })
+        .map(|name| {
+            name.chars()
+                .map(|ch| match ch {
+                    ch if ch.is_ascii_alphanumeric() || "._".contains(ch) => ch,
+                    _ => '_',
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("-");
+    format!("{name}.xml")
+}
+
+/// Render the report as JUnit XML with a `testsuite` per markdown file and a `testcase` per section.
+pub fn to_xml(report: &Report) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        "<testsuites tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\">",
+        report.sections().count(),
+        count(report.sections(), Status::Failed),
+        count(report.sections(), Status::Skipped),
+        report.files.iter().map(|file| file.duration.as_secs_f64()).sum::<f64>()
+    );
+    for file in &report.files {
+        write_suite(&mut xml, file);
+    }
+    xml.push_str("</testsuites>\n");
+    xml
+}
+
+fn write_suite(xml: &mut String, file: &FileReport) {
+    let path = file.path.display().to_string();
+    let _ = writeln!(
+        xml,
+        "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\">",
+        escape(&path),
+        file.sections.len(),
+        count(file.sections.iter(), Status::Failed),
+        count(file.sections.iter(), Status::Skipped),
+        file.duration.as_secs_f64()
+    );
+
+    for section in &file.sections {
+        // The failed command locates the failure, otherwise the first command locates the section
+        let failed = section.commands.iter().find(|command| command.status == Status::Failed);
+        let line = failed
+            .or(section.commands.first())
+            .and_then(|command| command.source_line);
+
+        // The cases before the first heading belong to an untitled section
+        let name = match section.title.as_str() {
+            "" => file.path.file_name().unwrap_or_default().to_string_lossy(),
+            title => title.into(),
+        };
+        let _ = write!(
+            xml,
+            "    <testcase name=\"{}\" classname=\"{}\" file=\"{}\"",
+            escape(&name),
+            escape(&path),
+            escape(&path)
+        );
+        if let Some(line) = line {
+            let _ = write!(xml, " line=\"{line}\"");
+        }
+        let _ = write!(xml, " time=\"{:.3}\"", section.duration.as_secs_f64());
+
+        match section.status {
+            Status::Passed => xml.push_str("/>\n"),
+            Status::Skipped => {
+                let reason = section
+                    .commands
+                    .iter()
+                    .find_map(|command| command.skip_reason.as_deref())
+                    .unwrap_or_default();
+                let _ = writeln!(
+                    xml,
+                    ">\n      <skipped message=\"{}\"/>\n    </testcase>",
+                    escape(reason)
+                );
+            },
+            Status::Failed => {
+                let location = format!("{path}:{}", line.unwrap_or_default());
+                let message = failed
+                    .and_then(|command| command.failure.as_deref())
+                    .or(section.failure.as_deref())
+                    .and_then(|failure| failure.lines().next())
+                    .unwrap_or("failed");
+                let _ = writeln!(
+                    xml,
+                    ">\n      <failure message=\"{}\">{}</failure>\n    </testcase>",
+                    escape(&format!("{location}: {message}")),
+                    escape(&failure_text(section, failed, &location))
+                );
+            },
+        }
+    }
+    xml.push_str("  </testsuite>\n");
+}
+
+/// Source location, command and diff of the outputs of the failed command, followed by the section failure.
+fn failure_text(section: &SectionReport, failed: Option<&CommandReport>, location: &str) -> String {
+    let mut text = format!("{location}\n");
+    if let Some(command) = failed {
+        let _ = writeln!(text, "$ {}", command.command);
+        match (&command.expected, &command.actual) {
+            (Some(expected), Some(actual)) if expected != actual => {
+                text.push_str("--- expected\n+++ actual\n");
+                text.push_str(&diff_lines(expected, actual));
+            },
+            _ => {
+                if let Some(failure) = &command.failure {
+                    let _ = writeln!(text, "{failure}");
+                }
+            },
+        }
+    }
+    if let Some(failure) = &section.failure
+        && failed.and_then(|command| command.failure.as_ref()) != Some(failure)
+    {
+        let _ = writeln!(text, "{failure}");
+    }
+    text
+}
+
+/// Line diff of the texts, the lines only in the expected text start with `-` and the lines only in the actual
+/// text start with `+`.
+fn diff_lines(expected: &str, actual: &str) -> String {
+    let expected = expected.lines().collect::<Vec<_>>();
+    let actual = actual.lines().collect::<Vec<_>>();
+
+    // Lengths of the longest common subsequences of the line suffixes
+    let mut lengths = vec![vec![0usize; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            lengths[i][j] = if expected[i] == actual[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            let _ = writeln!(diff, " {}", expected[i]);
+            (i, j) = (i + 1, j + 1);
+        } else if i < expected.len() && (j == actual.len() || lengths[i + 1][j] >= lengths[i][j + 1]) {
+            let _ = writeln!(diff, "-{}", expected[i]);
+            i += 1;
+        } else {
+            let _ = writeln!(diff, "+{}", actual[j]);
+            j += 1;
+        }
+    }
+    diff
+}
+
+fn count<'a>(sections: impl Iterator<Item = &'a SectionReport>, status: Status) -> usize {
+    sections.filter(|section| section.status == status).count()
+}
+
+/// Escape the text for an XML attribute or element, dropping the control characters that XML does not allow.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\n' | '\r' | '\t' => escaped.push(ch),
+            ch if ch.is_control() => {},
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use super::{diff_lines, report_name, to_xml};
+    use crate::report::{CommandReport, FileReport, Report, SectionReport, Status};
+
+    #[test]
+    fn name_reports() {
+        assert_eq!(report_name("README.md".as_ref()), "README.md.xml");
+        assert_eq!(report_name("docs/a/README.md".as_ref()), "docs-a-README.md.xml");
+        assert_eq!(report_name("../docs/my guide.md".as_ref()), "..-docs-my_guide.md.xml");
+        assert_eq!(
+            report_name(&std::env::current_dir().unwrap().join("docs/b/README.md")),
+            "docs-b-README.md.xml"
+        );
+    }
+
+    #[test]
+    fn diff_outputs() {
+        assert_eq!(diff_lines("a\nb\nc\n", "a\nx\nc\n"), " a\n-b\n+x\n c\n");
+        assert_eq!(diff_lines("a\n", "a\nb\n"), " a\n+b\n");
+    }
+
+    #[test]
+    fn render_sections() {
+        let command = |status, line| CommandReport {
+            command: "todo list".into(),
+            status,
+            source_line: Some(line),
+            ..Default::default()
+        };
+        let failed = CommandReport {
+            expected: Some("a <b>\n".into()),
+            actual: Some("a <c>\n".into()),
+            failure: Some("assertion `left == right` failed".into()),
+            ..command(Status::Failed, 12)
+        };
+        let skipped = CommandReport {
+            skip_reason: Some("skipped by directive".into()),
+            ..command(Status::Skipped, 20)
+        };
+        let report = Report {
+            files: vec![FileReport {
+                path: PathBuf::from("tests/todo.md"),
+                sections: vec![
+                    SectionReport::new("Passed", vec![command(Status::Passed, 4)], None),
+                    SectionReport::new("Failed \"list\"", vec![command(Status::Passed, 8), failed], None),
+                    SectionReport::new("Skipped", vec![skipped], None),
+                ],
+                duration: Duration::from_millis(1500),
+            }],
+        };
+
+        assert_eq!(
+            to_xml(&report),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites tests="3" failures="1" skipped="1" time="1.500">
+  <testsuite name="tests/todo.md" tests="3" failures="1" skipped="1" time="1.500">
+    <testcase name="Passed" classname="tests/todo.md" file="tests/todo.md" line="4" time="0.000"/>
+    <testcase name="Failed &quot;list&quot;" classname="tests/todo.md" file="tests/todo.md" line="12" time="0.000">
+      <failure message="tests/todo.md:12: assertion `left == right` failed">tests/todo.md:12
+$ todo list
+--- expected
++++ actual
+-a &lt;b&gt;
++a &lt;c&gt;
+</failure>
+    </testcase>
+    <testcase name="Skipped" classname="tests/todo.md" file="tests/todo.md" line="20" time="0.000">
+      <skipped message="skipped by directive"/>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#
+        );
+    }
+}
@@ -0,0 +1,372 @@
+use std::iter::Peekable;
+use std::ops::Range;
+use std::str::CharIndices;
+
+use thiserror::Error;
+
+use crate::cmd::WriteMode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    /// `|`
+    Pipe,
+    /// `&&`
+    And,
+    /// `||`
+    Or,
+    /// `;`
+    Semicolon,
+    /// `&`
+    Background,
+    /// `>`, `1>`, `>>` or `1>>`
+    RedirectStdout(WriteMode),
+    /// `2>` or `2>>`
+    RedirectStderr(WriteMode),
+    /// `&>` or `&>>`
+    RedirectAll(WriteMode),
+    /// `2>&1`
+    StderrToStdout,
+}
+
+/// Operators ordered so that longer ones are matched first.
+const OPERATORS: [(&str, Operator); 14] = [
+    ("2>&1", Operator::StderrToStdout),
+    ("&>>", Operator::RedirectAll(WriteMode::Append)),
+    ("1>>", Operator::RedirectStdout(WriteMode::Append)),
+    ("2>>", Operator::RedirectStderr(WriteMode::Append)),
+    ("&&", Operator::And),
+    ("||", Operator::Or),
+    ("&>", Operator::RedirectAll(WriteMode::Truncate)),
+    (">>", Operator::RedirectStdout(WriteMode::Append)),
+    ("1>", Operator::RedirectStdout(WriteMode::Truncate)),
+    ("2>", Operator::RedirectStderr(WriteMode::Truncate)),
+    ("|", Operator::Pipe),
+    ("&", Operator::Background),
+    (";", Operator::Semicolon),
+    (">", Operator::RedirectStdout(WriteMode::Truncate)),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenKind {
+    Word(String),
+    Operator(Operator),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    /// Byte range of the token in the source command line.
+    pub span: Range<usize>,
+}
+
+impl Token {
+    pub fn word(&self) -> Option<&str> {
+        match &self.kind {
+            TokenKind::Word(word) => Some(word),
+            TokenKind::Operator(_) => None,
+        }
+    }
+
+    pub fn operator(&self) -> Option<Operator> {
+        match self.kind {
+            TokenKind::Word(_) => None,
+            TokenKind::Operator(operator) => Some(operator),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum LexError {
+    #[error("unterminated {quote} quote started at {start}")]
+    UnterminatedQuote { quote: char, start: usize },
+
+    #[error("unterminated raw string started at {start}")]
+    UnterminatedRawString { start: usize },
+
+    #[error("unterminated escape at {start}")]
+    UnterminatedEscape { start: usize },
+}
+
+/// Split a command line into words and operators, like a POSIX shell does.
+///
+/// Supported are single and double quotes, backslash escapes, words glued from several quoted fragments (e.g.
+/// `--name="a b"`) and Rust-style raw strings `r#"…"#` at the beginning of a word, which may contain quotes and
+/// new lines as is. Unquoted `|`, `&`, `;` and `>` are operators and always separate words.
+pub fn tokenize(source: &str) -> Result<Vec<Token>, LexError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+
+    while let Some(&(start, ch)) = chars.peek() {
+        if ch.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        if let Some((text, operator)) = OPERATORS.iter().find(|(text, _)| source[start..].starts_with(text)) {
+            skip_to(&mut chars, start + text.len());
+            tokens.push(Token {
+                kind: TokenKind::Operator(*operator),
+                span: start..start + text.len(),
+            });
+            continue;
+        }
+
+        let word = lex_word(source, &mut chars)?;
+        let end = chars.peek().map(|(idx, _)| *idx).unwrap_or(source.len());
+        tokens.push(Token {
+            kind: TokenKind::Word(word),
+            span: start..end,
+        });
+    }
+
+    Ok(tokens)
+}
+
+/// Get the part of the source command line covered by the tokens.
+pub fn tokens_source<'a>(source: &'a str, tokens: &[Token]) -> &'a str {
+    match (tokens.first(), tokens.last()) {
+        (Some(first), Some(last)) => &source[first.span.start..last.span.end],
+        _ => "",
+    }
+}
+
+/// Quote the word for a POSIX shell, so that it is read back as a single word. Words of only safe characters,
+/// e.g. `./todo.toml`, are left as is.
+pub fn shell_quote(word: &str) -> String {
+    let is_safe = |ch: char| ch.is_ascii_alphanumeric() || "_./=:,@%+-".contains(ch);
+    if !word.is_empty() && word.chars().all(is_safe) {
+        word.into()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+fn lex_word(source: &str, chars: &mut Peekable<CharIndices>) -> Result<String, LexError> {
+    let mut word = String::new();
+
+    if let Some(&(start, _)) = chars.peek()
+        && let Some((prefix_len, hashes)) = raw_string_prefix(&source[start..])
+    {
+        let content_start = start + prefix_len;
+        let terminator = format!("\"{}", "#".repeat(hashes));
+        let content_len = source[content_start..]
+            .find(&terminator)
+            .ok_or(LexError::UnterminatedRawString { start })?;
+
+        word.push_str(&source[content_start..content_start + content_len]);
+        skip_to(chars, content_start + content_len + terminator.len());
+    }
+
+    while let Some(&(start, ch)) = chars.peek() {
+        match ch {
+            ch if ch.is_whitespace() => break,
+            '|' | '&' | ';' | '>' => break,
+            '\'' => {
+                chars.next();
+                loop {
+                    match chars.next() {
+                        Some((_, '\'')) => break,
+                        Some((_, ch)) => word.push(ch),
+                        None => return Err(LexError::UnterminatedQuote { quote: '\'', start }),
+                    }
+                }
+            },
+            '"' => {
+                chars.next();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((escape_start, '\\')) => match chars.peek() {
+                            Some((_, ch @ ('"' | '\\' | '$' | '`'))) => {
+                                word.push(*ch);
+                                chars.next();
+                            },
+                            Some((_, '\n')) => {
+                                chars.next();
+                            },
+                            Some(_) => word.push('\\'),
+                            None => return Err(LexError::UnterminatedEscape { start: escape_start }),
+                        },
+                        Some((_, ch)) => word.push(ch),
+                        None => return Err(LexError::UnterminatedQuote { quote: '"', start }),
+                    }
+                }
+            },
+            '\\' => {
+                chars.next();
+                match chars.next() {
+                    Some((_, '\n')) => {},
+                    Some((_, ch)) => word.push(ch),
+                    None => return Err(LexError::UnterminatedEscape { start }),
+                }
+            },
+            ch => {
+                word.push(ch);
+                chars.next();
+            },
+        }
+    }
+
+    Ok(word)
+}
+
+/// Match the beginning of a raw string, i.e. `r"`, `r#"`, `#"`, `r##"` and so on.
+/// Returns the length of the prefix and the number of hashes in it.
+fn raw_string_prefix(source: &str) -> Option<(usize, usize)> {
+    let without_r = source.strip_prefix('r').unwrap_or(source);
+    let hashes = without_r.len() - without_r.trim_start_matches('#').len();
+
+    let is_raw = without_r.len() < source.len() || hashes > 0;
+    (is_raw && without_r[hashes..].starts_with('"')).then(|| (source.len() - without_r.len() + hashes + 1, hashes))
+}
+
+fn skip_to(chars: &mut Peekable<CharIndices>, position: usize) {
+    while chars.next_if(|(idx, _)| *idx < position).is_some() {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LexError, Operator, TokenKind, shell_quote, tokenize};
+    use crate::cmd::WriteMode;
+
+    fn words(source: &str) -> Vec<String> {
+        tokenize(source)
+            .unwrap()
+            .into_iter()
+            .map(|token| match token.kind {
+                TokenKind::Word(word) => word,
+                TokenKind::Operator(operator) => format!("<{operator:?}>"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tokenize_words() {
+        let cases: &[(&str, &[&str])] = &[
+            ("mkdir a b c", &["mkdir", "a", "b", "c"]),
+            ("cd a/b cd \"ef g\"", &["cd", "a/b", "cd", "ef g"]),
+            ("echo \"test A\"", &["echo", "test A"]),
+            ("echo a \"b c d\" ef", &["echo", "a", "b c d", "ef"]),
+            ("  echo\t a  \n b ", &["echo", "a", "b"]),
+            ("echo 'single quoted' 'with \"double\"'", &[
+                "echo",
+                "single quoted",
+                "with \"double\"",
+            ]),
+            ("echo 'no \\escapes'", &["echo", "no \\escapes"]),
+            ("todo new --name=\"a b\"", &["todo", "new", "--name=a b"]),
+            ("echo a\"b c\"'d e'f", &["echo", "ab cd ef"]),
+            ("echo \"\" ''", &["echo", "", ""]),
+            ("echo \"say \\\"hi\\\"\"", &["echo", "say \"hi\""]),
+            ("echo \"back\\\\slash\" \"keep \\n\"", &[
+                "echo",
+                "back\\slash",
+                "keep \\n",
+            ]),
+            ("echo a\\ b \\\"c\\\"", &["echo", "a b", "\"c\""]),
+            ("echo multi\\\nline", &["echo", "multiline"]),
+            ("echo #tag tag# a#b", &["echo", "#tag", "tag#", "a#b"]),
+            ("echo \"#quoted#\"", &["echo", "#quoted#"]),
+            ("echo r#\"raw \"quoted\" text\"#", &["echo", "raw \"quoted\" text"]),
+            ("echo r#\"\nfirst\nsecond\n\"# > file", &[
+                "echo",
+                "\nfirst\nsecond\n",
+                "<RedirectStdout(Truncate)>",
+                "file",
+            ]),
+            ("echo #\"hash only\"# r\"raw\\n\"", &["echo", "hash only", "raw\\n"]),
+            ("echo r##\"with \"# inside\"##", &["echo", "with \"# inside"]),
+            ("echo r#\"a\"# r#\"b\"#", &["echo", "a", "b"]),
+            ("echo for\"x\" r", &["echo", "forx", "r"]),
+            ("echo \"a|b\" 'c;d' e\\&f \">\"", &["echo", "a|b", "c;d", "e&f", ">"]),
+        ];
+
+        for (source, expected) in cases {
+            assert_eq!(words(source), *expected, "source: {source:?}");
+        }
+    }
+
+    #[test]
+    fn tokenize_operators() {
+        let cases: &[(&str, &[&str])] = &[
+            ("a | b", &["a", "<Pipe>", "b"]),
+            ("a|b", &["a", "<Pipe>", "b"]),
+            ("a && b || c ; d", &["a", "<And>", "b", "<Or>", "c", "<Semicolon>", "d"]),
+            ("cd a; ls", &["cd", "a", "<Semicolon>", "ls"]),
+            ("serve &", &["serve", "<Background>"]),
+            ("a > f", &["a", "<RedirectStdout(Truncate)>", "f"]),
+            ("a >f", &["a", "<RedirectStdout(Truncate)>", "f"]),
+            ("a 1> f 1>> g", &[
+                "a",
+                "<RedirectStdout(Truncate)>",
+                "f",
+                "<RedirectStdout(Append)>",
+                "g",
+            ]),
+            ("a >> f", &["a", "<RedirectStdout(Append)>", "f"]),
+            ("a 2> f 2>> g", &[
+                "a",
+                "<RedirectStderr(Truncate)>",
+                "f",
+                "<RedirectStderr(Append)>",
+                "g",
+            ]),
+            ("a 2>&1", &["a", "<StderrToStdout>"]),
+            ("a &> f &>> g", &[
+                "a",
+                "<RedirectAll(Truncate)>",
+                "f",
+                "<RedirectAll(Append)>",
+                "g",
+            ]),
+            ("echo 12> f", &["echo", "12", "<RedirectStdout(Truncate)>", "f"]),
+            ("echo a2 > f", &["echo", "a2", "<RedirectStdout(Truncate)>", "f"]),
+        ];
+
+        for (source, expected) in cases {
+            assert_eq!(words(source), *expected, "source: {source:?}");
+        }
+    }
+
+    #[test]
+    fn tokenize_spans() {
+        let tokens = tokenize("echo \"a b\"|cat").unwrap();
+        let spans = tokens.iter().map(|token| token.span.clone()).collect::<Vec<_>>();
+        assert_eq!(spans, vec![0..4, 5..10, 10..11, 11..14]);
+        assert_eq!(tokens[2].operator(), Some(Operator::Pipe));
+        assert_eq!(tokens[1].word(), Some("a b"));
+
+        let tokens = tokenize("echo r#\"x\"# 2>>log").unwrap();
+        assert_eq!(tokens[1].span, 5..11);
+        assert_eq!(tokens[2].operator(), Some(Operator::RedirectStderr(WriteMode::Append)));
+        assert_eq!(tokens[3].span, 15..18);
+    }
+
+    #[test]
+    fn tokenize_errors() {
+        let cases = [
+            ("echo \"abc", LexError::UnterminatedQuote { quote: '"', start: 5 }),
+            ("echo 'abc", LexError::UnterminatedQuote { quote: '\'', start: 5 }),
+            ("echo a'b", LexError::UnterminatedQuote { quote: '\'', start: 6 }),
+            ("echo r#\"abc\"", LexError::UnterminatedRawString { start: 5 }),
+            ("echo r##\"abc\"#", LexError::UnterminatedRawString { start: 5 }),
+            ("echo abc\\", LexError::UnterminatedEscape { start: 8 }),
+            ("echo \"abc\\", LexError::UnterminatedEscape { start: 9 }),
+        ];
+
+        for (source, expected) in cases {
+            assert_eq!(tokenize(source), Err(expected), "source: {source:?}");
+        }
+    }
+
+    #[test]
+    fn quote_words() {
+        assert_eq!(shell_quote("./todo.toml"), "./todo.toml");
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("it's a b"), r"'it'\''s a b'");
+
+        for word in ["", "a b", "it's", "$HOME", "a|b", "~"] {
+            assert_eq!(words(&shell_quote(word)), [word]);
+        }
+    }
+}
@@ -1,11 +1,44 @@
+use std::any::Any;
 use std::ffi::OsString;
-use std::path::PathBuf;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{env, fs, io};
 
 use temp_testdir::TempDir;
 
+use crate::ansi::AnsiMode;
+use crate::case::{TestCase, TestSection};
+use crate::command_stub::CommandStub;
+use crate::config::{ConfigPrecedence, FileConfig, ProjectConfig};
+use crate::error::TestError;
+use crate::job::Jobs;
+use crate::normalize::{Normalizer, Replace};
+use crate::report::{FileReport, Recorder, Report, SectionReport, panic_message};
+use crate::stub::StubServer;
+
+pub mod ansi;
 pub mod case;
 pub mod cmd;
+pub mod command_stub;
+pub mod condition;
+pub mod config;
+pub mod directive;
 pub mod error;
+pub mod hermetic;
+pub mod job;
+pub mod junit;
+pub mod lexer;
+pub mod normalize;
+pub mod pty;
+pub mod replay;
+pub mod report;
+pub mod stub;
+
+pub const KEEP_DIR_ENV: &str = "MD_CLI_TEST_KEEP_DIR";
+pub const REPLAY_ENV: &str = "MD_CLI_TEST_REPLAY";
+pub const JUNIT_ENV: &str = "MD_CLI_TEST_JUNIT";
 
 #[derive(Debug, Clone)]
 pub struct Tester {
@@ -13,6 +46,30 @@ pub struct Tester {
     pub cargo_bin_alias: Option<String>,
     pub cargo_bin_name: Option<String>,
     pub envs: Vec<(OsString, OsString)>,
+    pub fixture_dirs: Vec<PathBuf>,
+    pub keep_failed_dir: bool,
+    pub replay_script: bool,
+    /// Languages of the code blocks with commands, `sh` and `shell` if empty.
+    pub languages: Vec<String>,
+    pub config_precedence: ConfigPrecedence,
+    /// Maximum duration of each command unless set by a directive.
+    pub timeout: Option<Duration>,
+    /// Normalizers of the output of the commands and the expected output.
+    pub normalizers: Vec<Arc<dyn Normalizer>>,
+    /// Directory of the `md-cli-test.toml` file, the crate root if not set.
+    pub project_dir: Option<PathBuf>,
+    /// Handling of the ANSI escape sequences in the output unless set by a directive.
+    pub ansi_mode: Option<AnsiMode>,
+    /// Enabled cargo features checked by the `feature=<name>` conditions of code blocks.
+    pub features: Vec<String>,
+    /// Executables on the `PATH` of the tested binary, overridden by the stub commands of the markdown.
+    pub command_stubs: Vec<CommandStub>,
+    /// Whether the binaries run in an isolated environment, see [`Tester::hermetic`].
+    pub hermetic: bool,
+    /// Environment variables inherited by the binaries in the hermetic mode.
+    pub allowed_envs: Vec<String>,
+    /// Path of the JUnit XML report, see [`Tester::with_junit_report`].
+    pub junit_path: Option<PathBuf>,
 }
 
 impl Tester {
@@ -22,6 +79,23 @@ impl Tester {
             cargo_bin_alias: None,
             cargo_bin_name: None,
             envs: Vec::new(),
+            fixture_dirs: Vec::new(),
+            keep_failed_dir: false,
+            replay_script: false,
+            languages: Vec::new(),
+            config_precedence: ConfigPrecedence::default(),
+            timeout: None,
+            normalizers: Vec::new(),
+            project_dir: None,
+            features: Vec::new(),
+            ansi_mode: None,
+            command_stubs: Vec::new(),
+            hermetic: false,
+            allowed_envs: hermetic::DEFAULT_ALLOWED_ENVS
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            junit_path: None,
         }
     }
 
@@ -47,31 +121,544 @@ impl Tester {
         self
     }
 
+    /// Copy the content of the directory into the test directory of every section before its first test case.
+    pub fn with_fixture_dir(mut self, fixture_dir: impl Into<PathBuf>) -> Self {
+        self.fixture_dirs.push(fixture_dir.into());
+        self
+    }
+
+    /// Keep the test directory of a failed section for debugging and print its path. It can also be enabled
+    /// with the `MD_CLI_TEST_KEEP_DIR` environment variable.
+    pub fn with_keep_failed_dir(mut self, keep_failed_dir: bool) -> Self {
+        self.keep_failed_dir = keep_failed_dir;
+        self
+    }
+
+    /// Write the `replay.sh` script with the executed commands into the kept test directory of a failed section.
+    /// It can also be enabled with the `MD_CLI_TEST_REPLAY` environment variable. Implies keeping the directory.
+    pub fn with_replay_script(mut self, replay_script: bool) -> Self {
+        self.replay_script = replay_script;
+        self
+    }
+
+    /// Treat the code blocks of the language, e.g. `console`, as test cases instead of the `sh` and `shell` blocks.
+    pub fn with_language(mut self, language: impl Into<String>) -> Self {
+        self.languages.push(language.into());
+        self
+    }
+
+    /// Choose whether the builder options or the front matter of the markdown file win when both set an option.
+    /// The builder options win by default.
+    pub fn with_config_precedence(mut self, config_precedence: ConfigPrecedence) -> Self {
+        self.config_precedence = config_precedence;
+        self
+    }
+
+    /// Fail the commands that run longer than the timeout, unless a directive sets another one.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Replace the string in the output of the commands before comparing it to the expected output.
+    pub fn with_replacement(self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.with_normalizer(Replace {
+            from: from.into(),
+            to: to.into(),
+        })
+    }
+
+    /// Strip the ANSI escape sequences from the output of the commands or render them into a readable markup,
+    /// unless a directive sets another mode.
+    pub fn with_ansi_mode(mut self, ansi_mode: AnsiMode) -> Self {
+        self.ansi_mode = Some(ansi_mode);
+        self
+    }
+
+    /// Transform the actual and the expected output with the normalizer before comparing them, e.g.
+    /// `normalize::Timestamps`. The normalizers are applied in the order of registration.
+    pub fn with_normalizer(mut self, normalizer: impl Normalizer + 'static) -> Self {
+        self.normalizers.push(Arc::new(normalizer));
+        self
+    }
+
+    /// Run the code blocks with the `feature=<name>` condition, e.g. if `cfg!(feature = "sqlite")` is true.
+    pub fn with_feature(mut self, feature: impl Into<String>) -> Self {
+        self.features.push(feature.into());
+        self
+    }
+
+    /// Put the stub command on the `PATH` of the tested binary, e.g. instead of `git`.
+    pub fn with_stub_command(mut self, stub: CommandStub) -> Self {
+        self.command_stubs.push(stub);
+        self
+    }
+
+    /// Run the binaries in an isolated environment, so that they produce the same output on every machine. The
+    /// inherited environment variables are cleared except the allowed ones, see [`Tester::with_allowed_env`].
+    /// `HOME`, the `XDG_*_HOME` directories and `TMPDIR` point to empty directories of each section, and `LANG`,
+    /// `TZ` and `SOURCE_DATE_EPOCH` are fixed. The envs set by the builder, the markdown or the config override them.
+    pub fn hermetic(mut self) -> Self {
+        self.hermetic = true;
+        self
+    }
+
+    /// Keep the environment variable of the test process in the hermetic mode, in addition to `PATH`.
+    pub fn with_allowed_env(mut self, name: impl Into<String>) -> Self {
+        self.allowed_envs.push(name.into());
+        self
+    }
+
+    /// Write the results as JUnit XML with a `testcase` per section into the directory, e.g. to
+    /// `docs-README.md.xml` for `docs/README.md`. The directory can also be set by the `MD_CLI_TEST_JUNIT`
+    /// environment variable.
+    pub fn with_junit_report(mut self, path: impl Into<PathBuf>) -> Self {
+        self.junit_path = Some(path.into());
+        self
+    }
+
+    /// Look for the `md-cli-test.toml` config file in the directory instead of the crate root.
+    pub fn with_project_dir(mut self, project_dir: impl Into<PathBuf>) -> Self {
+        self.project_dir = Some(project_dir.into());
+        self
+    }
+
+    /// Merge the settings of the project config file into the options, they have the lowest precedence.
+    /// The relative fixture directories are resolved against the `project_dir`.
+    pub fn merge_project_config(mut self, config: ProjectConfig, project_dir: &Path) -> Self {
+        let ProjectConfig {
+            cargo_bin_alias,
+            cargo_bin_name,
+            env,
+            languages,
+            fixture_dirs,
+            timeout,
+            replace,
+            ansi,
+            normalize,
+        } = config;
+
+        self.cargo_bin_alias = self.cargo_bin_alias.or(cargo_bin_alias);
+        self.cargo_bin_name = self.cargo_bin_name.or(cargo_bin_name);
+        self.envs
+            .splice(0..0, env.into_iter().map(|(key, val)| (key.into(), val.into())));
+        if self.languages.is_empty() {
+            self.languages = languages;
+        }
+        self.fixture_dirs
+            .splice(0..0, fixture_dirs.into_iter().map(|dir| project_dir.join(dir)));
+        self.timeout = self.timeout.or(timeout.map(Duration::from_secs_f64));
+        self.ansi_mode = self.ansi_mode.or(ansi);
+        let replacements = replace.into_iter().map(|replacement| {
+            Arc::new(Replace {
+                from: replacement.from,
+                to: replacement.to,
+            }) as Arc<dyn Normalizer>
+        });
+        let normalizers = normalize.iter().filter_map(|name| normalize::builtin(name));
+        self.normalizers.splice(0..0, normalizers.chain(replacements));
+        self
+    }
+
+    /// Merge the front matter settings of the markdown file into the builder options.
+    pub fn merge_file_config(mut self, config: FileConfig) -> Self {
+        let FileConfig {
+            cargo_bin_alias,
+            cargo_bin_name,
+            env,
+            languages,
+        } = config;
+        let envs = env.into_iter().map(|(key, val)| (key.into(), val.into()));
+
+        // The later envs override the earlier ones
+        match self.config_precedence {
+            ConfigPrecedence::Builder => {
+                self.cargo_bin_alias = self.cargo_bin_alias.or(cargo_bin_alias);
+                self.cargo_bin_name = self.cargo_bin_name.or(cargo_bin_name);
+                self.envs.splice(0..0, envs);
+                if self.languages.is_empty() {
+                    self.languages = languages;
+                }
+            },
+            ConfigPrecedence::File => {
+                self.cargo_bin_alias = cargo_bin_alias.or(self.cargo_bin_alias);
+                self.cargo_bin_name = cargo_bin_name.or(self.cargo_bin_name);
+                self.envs.extend(envs);
+                if !languages.is_empty() {
+                    self.languages = languages;
+                }
+            },
+        }
+        self
+    }
+
+    /// Run the markdown file, stopping at the first failed section. If the JUnit report is enabled, all sections
+    /// run and the first failure is returned after the report is written.
     pub fn run(self) -> error::Result<()> {
-        let sections = case::parse_markdown_tests(
-            self.md_file_path,
-            self.cargo_bin_alias,
-            self.cargo_bin_name,
-            Some(self.envs),
+        let started = Instant::now();
+        let (tester, content) = self.load()?;
+        let junit_path = tester.junit_path();
+        let (sections, failure) = tester.run_markdown(&content, junit_path.is_none())?;
+
+        if let Some(junit_path) = junit_path {
+            tester.write_junit_report(&junit_path, sections, started)?;
+        }
+        match failure {
+            Some(SectionFailure::Error(err)) => Err(err),
+            Some(SectionFailure::Panic(panic)) => panic::resume_unwind(panic),
+            None => Ok(()),
+        }
+    }
+
+    /// Run all sections of the markdown file and report their results. A failed section does not stop the next
+    /// ones, only an unreadable file or an invalid config returns an error.
+    pub fn execute(self) -> error::Result<Report> {
+        let started = Instant::now();
+        let (tester, content) = self.load()?;
+        let (sections, _) = tester.run_markdown(&content, false)?;
+        let report = tester.report(sections, started);
+
+        if let Some(junit_path) = tester.junit_path() {
+            junit::write_report(&report, &junit_path).map_err(|err| junit_error(&junit_path, err))?;
+        }
+        Ok(report)
+    }
+
+    fn report(&self, sections: Vec<SectionReport>, started: Instant) -> Report {
+        Report {
+            files: vec![FileReport {
+                path: self.md_file_path.clone(),
+                sections,
+                duration: started.elapsed(),
+            }],
+        }
+    }
+
+    fn junit_path(&self) -> Option<PathBuf> {
+        self.junit_path.clone().or_else(|| {
+            env::var_os(JUNIT_ENV)
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from)
+        })
+    }
+
+    fn write_junit_report(&self, path: &Path, sections: Vec<SectionReport>, started: Instant) -> error::Result<()> {
+        junit::write_report(&self.report(sections, started), path).map_err(|err| junit_error(path, err))
+    }
+
+    /// Read the markdown file and merge the config of its front matter and of the project.
+    fn load(self) -> error::Result<(Self, String)> {
+        let content = fs::read_to_string(&self.md_file_path)?;
+        let file_config = config::front_matter(&content)
+            .map(|front_matter| front_matter.parse_config())
+            .transpose()
+            .map_err(|reason| TestError::Config {
+                path: self.md_file_path.clone(),
+                reason,
+            })?;
+        let mut tester = match file_config {
+            Some(file_config) => self.merge_file_config(file_config),
+            None => self,
+        };
+
+        let project_dir = tester
+            .project_dir
+            .clone()
+            .or_else(|| env::var_os("CARGO_MANIFEST_DIR").map(PathBuf::from));
+        if let Some(project_dir) = project_dir {
+            let project_config = ProjectConfig::load(&project_dir).map_err(|reason| TestError::Config {
+                path: project_dir.join(config::PROJECT_CONFIG_FILE),
+                reason,
+            })?;
+            if let Some(project_config) = project_config {
+                tester = tester.merge_project_config(project_config, &project_dir);
+            }
+        }
+
+        Ok((tester, content))
+    }
+
+    /// Run the sections and return their reports with the first failure. The sections after a failure run only
+    /// unless stopping at the failure.
+    fn run_markdown(
+        &self,
+        content: &str,
+        stop_at_failure: bool,
+    ) -> error::Result<(Vec<SectionReport>, Option<SectionFailure>)> {
+        let mut sections = case::parse_markdown(
+            &self.md_file_path,
+            content,
+            self.cargo_bin_alias.clone(),
+            self.cargo_bin_name.clone(),
+            Some(self.envs.clone()),
+            &self.languages,
         )?;
+        for test_case in sections.iter_mut().flat_map(TestSection::all_cases_mut) {
+            test_case.options.timeout = test_case.options.timeout.or(self.timeout);
+            test_case.options.ansi = test_case.options.ansi.or(self.ansi_mode);
+            test_case.normalizers = self.normalizers.clone();
+            test_case.skip_reason = test_case.options.skip_reason(&self.features);
+        }
 
-        for section in sections {
+        let replay_script = self.replay_script || env_flag(REPLAY_ENV);
+        let keep_failed_dir = self.keep_failed_dir || replay_script || env_flag(KEEP_DIR_ENV);
+        let mut reports = Vec::new();
+        let mut first_failure = None;
+
+        for mut section in sections {
             let test_dir = TempDir::default();
-            let mut completed_tests = Vec::new();
+            let hermetic_dir = self
+                .hermetic
+                .then(|| TempDir::new(hermetic::hermetic_dir(&test_dir), true));
+            let recorder = Recorder::default();
+            let started = Instant::now();
+            let mut executed_cases = 0;
 
             log::debug!("\n# {}", section.title);
 
-            for test_case in section.cases {
-                let test_case = test_case.with_test_dir(test_dir.as_os_str());
+            for test_case in section.all_cases_mut() {
+                test_case.recorder = recorder.clone();
+            }
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                self.run_section(&mut section, &test_dir, hermetic_dir.as_deref(), &mut executed_cases)
+            }));
+
+            let kept_dir = (keep_failed_dir && !matches!(result, Ok(Ok(())))).then(|| {
+                hermetic_dir.map(TempDir::permanent);
+                self.keep_test_dir(test_dir, &section, executed_cases, replay_script)
+            });
+
+            let failure = match result {
+                Ok(Ok(())) => None,
+                Ok(Err(err)) => {
+                    let err = match kept_dir {
+                        Some(dir) => TestError::KeptDir {
+                            source: Box::new(err),
+                            dir,
+                        },
+                        None => err,
+                    };
+                    Some((err.to_string(), SectionFailure::Error(err)))
+                },
+                Err(panic) => match kept_dir {
+                    // The kept directory is appended to the message of the failed assertion like to an error
+                    Some(dir) => {
+                        let message = format!(
+                            "{}\nTest directory is kept at `{}`",
+                            panic_message(panic.as_ref()),
+                            dir.display()
+                        );
+                        Some((message.clone(), SectionFailure::Panic(Box::new(message))))
+                    },
+                    None => Some((panic_message(panic.as_ref()), SectionFailure::Panic(panic))),
+                },
+            };
+            let (message, failure) = failure.unzip();
+            reports.push(SectionReport {
+                duration: started.elapsed(),
+                ..SectionReport::new(section.title, recorder.take(), message)
+            });
+
+            if first_failure.is_none() {
+                first_failure = failure;
+            }
+            if stop_at_failure && first_failure.is_some() {
+                break;
+            }
+        }
+        Ok((reports, first_failure))
+    }
+
+    fn run_section(
+        &self,
+        section: &mut TestSection,
+        test_dir: &Path,
+        hermetic_dir: Option<&Path>,
+        executed_cases: &mut usize,
+    ) -> error::Result<()> {
+        for fixture_dir in self.fixture_dirs.iter().chain(&section.fixtures) {
+            case::copy_dir_content(fixture_dir, test_dir).map_err(|err| {
+                TestError::Failed(format!("Failed to copy fixture `{}`: {err}", fixture_dir.display()))
+            })?;
+        }
+
+        let stub_server = if section.stubs.is_empty() {
+            None
+        } else {
+            let server = StubServer::start(section.stubs.clone())
+                .map_err(|err| TestError::Failed(format!("Failed to start the stub server: {err}")))?;
+            log::debug!("Stub server listens at {}", server.url());
+            Some(server)
+        };
+
+        // The stub commands of the markdown are installed last to override the ones with the same names
+        let bin_dir = if self.command_stubs.is_empty() && section.command_stubs.is_empty() {
+            None
+        } else {
+            let bin_dir = TempDir::default();
+            for stub in self.command_stubs.iter().chain(&section.command_stubs) {
+                stub.install(&bin_dir).map_err(|err| {
+                    TestError::Failed(format!("Failed to install stub command `{}`: {err}", stub.name))
+                })?;
+            }
+            Some(bin_dir)
+        };
+
+        let hermetic_envs = match hermetic_dir {
+            Some(dir) => hermetic::isolated_envs(dir)
+                .map_err(|err| TestError::Failed(format!("Failed to create the hermetic environment: {err}")))?,
+            None => Vec::new(),
+        };
+
+        let TestSection {
+            setup, cases, teardown, ..
+        } = section;
+        let jobs = Jobs::default();
+        for test_case in setup.iter_mut().chain(cases.iter_mut()).chain(teardown.iter_mut()) {
+            test_case.test_dir = Some(test_dir.into());
+            test_case.jobs = jobs.clone();
+            test_case.stub_url = stub_server.as_ref().map(StubServer::url);
+            test_case.bin_dir = bin_dir.as_ref().map(|dir| dir.to_path_buf());
+            if hermetic_dir.is_some() {
+                test_case.envs.splice(0..0, hermetic_envs.iter().cloned());
+                test_case.allowed_envs = Some(self.allowed_envs.clone());
+            }
+        }
+
+        // The background jobs are killed after the teardown and before the test directory is removed
+        let _jobs = jobs.kill_on_drop();
+        let teardown = TeardownGuard::new(teardown);
+        for test_case in setup.iter().chain(cases.iter()) {
+            *executed_cases += 1;
+
+            log::debug!("Testing: {:?}", test_case.commands);
+            test_case.run()?;
+        }
+        teardown.run()?;
+
+        if let Some(stub) = stub_server.iter().flat_map(StubServer::unused_required_stubs).next() {
+            return Err(TestError::Failed(format!(
+                "Required stub `{} {}` in source {} was never requested",
+                stub.method,
+                stub.path,
+                stub.source_location()
+            )));
+        }
+        Ok(())
+    }
 
-                log::debug!("Testing: {:?}", test_case.commands);
-                test_case.run()?;
-                completed_tests.push(test_case);
+    fn keep_test_dir(
+        &self,
+        test_dir: TempDir,
+        section: &TestSection,
+        executed_cases: usize,
+        replay_script: bool,
+    ) -> PathBuf {
+        let test_dir = test_dir.permanent().to_path_buf();
+
+        if replay_script {
+            let script = replay::replay_script(self, section, executed_cases);
+            if let Err(err) = fs::write(test_dir.join("replay.sh"), script) {
+                log::warn!("Failed to write the replay script into `{}`: {err}", test_dir.display());
             }
+        }
 
-            // Destroy completed test cases
-            drop(completed_tests);
+        test_dir
+    }
+}
+
+/// Failure of a section, returned or resumed after the other sections run.
+enum SectionFailure {
+    Error(TestError),
+    Panic(Box<dyn Any + Send>),
+}
+
+fn junit_error(path: &Path, err: io::Error) -> TestError {
+    TestError::Failed(format!("Failed to write the JUnit report `{}`: {err}", path.display()))
+}
+
+/// Runs the teardown cases of a section when dropped, so that they also run after a failed or panicked test case.
+struct TeardownGuard<'a> {
+    cases: &'a [TestCase],
+    is_armed: bool,
+}
+
+impl<'a> TeardownGuard<'a> {
+    fn new(cases: &'a [TestCase]) -> Self {
+        Self { cases, is_armed: true }
+    }
+
+    /// Run the teardown cases after a successful section, reporting their failures.
+    fn run(mut self) -> error::Result<()> {
+        self.is_armed = false;
+        for test_case in self.cases {
+            log::debug!("Teardown: {:?}", test_case.commands);
+            test_case.run()?;
         }
         Ok(())
     }
 }
+
+impl Drop for TeardownGuard<'_> {
+    fn drop(&mut self) {
+        if !self.is_armed {
+            return;
+        }
+
+        // The section has already failed, so the teardown failures are only logged
+        for test_case in self.cases {
+            log::debug!("Teardown: {:?}", test_case.commands);
+            match panic::catch_unwind(AssertUnwindSafe(|| test_case.run())) {
+                Ok(Ok(())) => {},
+                Ok(Err(err)) => log::error!("Teardown failed: {err}"),
+                Err(_) => log::error!("Teardown panicked: {:?}", test_case.commands),
+            }
+        }
+    }
+}
+
+/// Check that the environment variable is set and not disabled with `0` or `false`.
+fn env_flag(name: &str) -> bool {
+    env::var(name).is_ok_and(|val| !val.is_empty() && val != "0" && !val.eq_ignore_ascii_case("false"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Tester;
+    use crate::config::{ConfigPrecedence, FileConfig};
+
+    #[test]
+    fn merge_file_config() {
+        let config = FileConfig {
+            cargo_bin_alias: Some("file".into()),
+            cargo_bin_name: Some("file-cli".into()),
+            env: [("A".into(), "file".into())].into(),
+            languages: vec!["console".into()],
+        };
+
+        let tester = Tester::new("test.md")
+            .with_cargo_bin_alias("builder")
+            .with_env("A", "builder")
+            .merge_file_config(config.clone());
+        assert_eq!(tester.cargo_bin_alias.as_deref(), Some("builder"));
+        assert_eq!(tester.cargo_bin_name.as_deref(), Some("file-cli"));
+        assert_eq!(tester.envs, [
+            ("A".into(), "file".into()),
+            ("A".into(), "builder".into())
+        ]);
+        assert_eq!(tester.languages, ["console"]);
+
+        let tester = Tester::new("test.md")
+            .with_cargo_bin_alias("builder")
+            .with_env("A", "builder")
+            .with_language("sh")
+            .with_config_precedence(ConfigPrecedence::File)
+            .merge_file_config(config);
+        assert_eq!(tester.cargo_bin_alias.as_deref(), Some("file"));
+        assert_eq!(tester.envs, [
+            ("A".into(), "builder".into()),
+            ("A".into(), "file".into())
+        ]);
+        assert_eq!(tester.languages, ["console"]);
+    }
+}
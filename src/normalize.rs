@@ -0,0 +1,306 @@
+use std::fmt::Debug;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Transformation of the actual and the expected output of a command before they are compared, e.g. to replace
+/// volatile values with stable placeholders.
+pub trait Normalizer: Debug + Send + Sync {
+    fn normalize(&self, text: &str, context: &NormalizeContext<'_>) -> String;
+}
+
+/// Where the compared output comes from.
+#[derive(Debug, Clone, Copy)]
+pub struct NormalizeContext<'a> {
+    /// Test directory of the section.
+    pub test_dir: &'a Path,
+    /// Directory where the command ran.
+    pub current_dir: &'a Path,
+}
+
+/// Normalizers applied before the registered ones.
+pub fn default_normalizers() -> [Arc<dyn Normalizer>; 2] {
+    [Arc::new(CurrentDirPath), Arc::new(MacosTempDir)]
+}
+
+/// Find the built-in normalizer by its name in the config: `timestamp`, `duration`, `uuid`, `hash` or `temp-dir`.
+pub fn builtin(name: &str) -> Option<Arc<dyn Normalizer>> {
+    Some(match name {
+        "timestamp" => Arc::new(Timestamps),
+        "duration" => Arc::new(Durations),
+        "uuid" => Arc::new(Uuids),
+        "hash" => Arc::new(HexHashes::default()),
+        "temp-dir" => Arc::new(TempDirPath::default()),
+        _ => return None,
+    })
+}
+
+/// Substitutes the `${current_dir_path}` placeholder with the directory where the command ran.
+#[derive(Debug)]
+pub struct CurrentDirPath;
+
+impl Normalizer for CurrentDirPath {
+    fn normalize(&self, text: &str, context: &NormalizeContext<'_>) -> String {
+        text.replace("${current_dir_path}", &context.current_dir.to_string_lossy())
+    }
+}
+
+/// On macOS, temporary directories may appear with a `/private` prefix, e.g., `/private/var/folders/...`, which
+/// causes mismatch with expected output defined as `/var/folders/...`.
+#[derive(Debug)]
+pub struct MacosTempDir;
+
+impl Normalizer for MacosTempDir {
+    fn normalize(&self, text: &str, _context: &NormalizeContext<'_>) -> String {
+        text.replace("/private/var/", "/var/")
+    }
+}
+
+/// Replaces the string.
+#[derive(Debug)]
+pub struct Replace {
+    pub from: String,
+    pub to: String,
+}
+
+impl Normalizer for Replace {
+    fn normalize(&self, text: &str, _context: &NormalizeContext<'_>) -> String {
+        text.replace(&self.from, &self.to)
+    }
+}
+
+/// Replaces the path of the test directory with the placeholder, `[temp_dir]` by default.
+#[derive(Debug)]
+pub struct TempDirPath {
+    pub placeholder: String,
+}
+
+impl Default for TempDirPath {
+    fn default() -> Self {
+        Self {
+            placeholder: "[temp_dir]".into(),
+        }
+    }
+}
+
+impl Normalizer for TempDirPath {
+    fn normalize(&self, text: &str, context: &NormalizeContext<'_>) -> String {
+        if context.test_dir.as_os_str().is_empty() {
+            return text.into();
+        }
+        text.replace(&*context.test_dir.to_string_lossy(), &self.placeholder)
+    }
+}
+
+/// Replaces the timestamps like `2024-01-31T12:34:56.789Z` or `2024-01-31 12:34:56 +01:00` with `[timestamp]`.
+#[derive(Debug)]
+pub struct Timestamps;
+
+impl Normalizer for Timestamps {
+    fn normalize(&self, text: &str, _context: &NormalizeContext<'_>) -> String {
+        replace_words(text, "[timestamp]", match_timestamp)
+    }
+}
+
+/// Replaces the durations like `15ms` or `1.52s` with `[duration]`.
+#[derive(Debug)]
+pub struct Durations;
+
+impl Normalizer for Durations {
+    fn normalize(&self, text: &str, _context: &NormalizeContext<'_>) -> String {
+        replace_words(text, "[duration]", match_duration)
+    }
+}
+
+/// Replaces the UUIDs like `67e55044-10b1-426f-9247-bb680e5fe0c8` with `[uuid]`.
+#[derive(Debug)]
+pub struct Uuids;
+
+impl Normalizer for Uuids {
+    fn normalize(&self, text: &str, _context: &NormalizeContext<'_>) -> String {
+        replace_words(text, "[uuid]", match_uuid)
+    }
+}
+
+/// Replaces the hexadecimal hashes of at least `min_len` digits, 7 by default, with `[hash]`. A hash must contain
+/// both decimal digits and letters, so neither numbers nor words are replaced.
+#[derive(Debug)]
+pub struct HexHashes {
+    pub min_len: usize,
+}
+
+impl Default for HexHashes {
+    fn default() -> Self {
+        Self { min_len: 7 }
+    }
+}
+
+impl Normalizer for HexHashes {
+    fn normalize(&self, text: &str, _context: &NormalizeContext<'_>) -> String {
+        replace_words(text, "[hash]", |text| {
+            let len = count_prefix(text, |ch| ch.is_ascii_hexdigit());
+            let hash = &text[..len];
+            (len >= self.min_len && hash.contains(|ch: char| ch.is_ascii_digit()) && hash.contains(char::is_alphabetic))
+                .then_some(len)
+        })
+    }
+}
+
+fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+/// Replace the matches that are not parts of longer words or numbers with the placeholder. The `matcher` returns the
+/// length of the match at the start of the text.
+fn replace_words(text: &str, placeholder: &str, matcher: impl Fn(&str) -> Option<usize>) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut prev = None;
+    let mut idx = 0;
+
+    while let Some(ch) = text[idx..].chars().next() {
+        if !prev.is_some_and(|prev| is_word_char(prev) || prev == '.')
+            && let Some(len) = matcher(&text[idx..]).filter(|len| !text[idx + len..].starts_with(is_word_char))
+        {
+            result.push_str(&text[copied..idx]);
+            result.push_str(placeholder);
+            idx += len;
+            copied = idx;
+            prev = text[..idx].chars().next_back();
+            continue;
+        }
+        prev = Some(ch);
+        idx += ch.len_utf8();
+    }
+
+    result.push_str(&text[copied..]);
+    result
+}
+
+fn count_prefix(text: &str, pred: impl Fn(char) -> bool) -> usize {
+    text.find(|ch| !pred(ch)).unwrap_or(text.len())
+}
+
+/// Match exactly `count` ASCII digits.
+fn digits(text: &str, count: usize) -> Option<&str> {
+    let len = count_prefix(text, |ch| ch.is_ascii_digit());
+    (len == count).then(|| &text[count..])
+}
+
+fn match_timestamp(text: &str) -> Option<usize> {
+    let rest = digits(text, 4)?.strip_prefix('-')?;
+    let rest = digits(rest, 2)?.strip_prefix('-')?;
+    let rest = digits(rest, 2)?;
+    let rest = rest.strip_prefix('T').or_else(|| rest.strip_prefix(' '))?;
+    let rest = digits(rest, 2)?.strip_prefix(':')?;
+    let mut rest = digits(rest, 2)?;
+
+    if let Some(seconds) = rest.strip_prefix(':').and_then(|rest| digits(rest, 2)) {
+        rest = seconds;
+        if let Some(fraction) = rest.strip_prefix(['.', ',']) {
+            let len = count_prefix(fraction, |ch| ch.is_ascii_digit());
+            if len > 0 {
+                rest = &fraction[len..];
+            }
+        }
+    }
+
+    if let Some(utc) = rest.strip_prefix('Z') {
+        rest = utc;
+    } else if let Some(offset) = rest
+        .trim_start_matches(' ')
+        .strip_prefix(['+', '-'])
+        .and_then(|offset| digits(offset, 4).or_else(|| digits(digits(offset, 2)?.strip_prefix(':')?, 2)))
+    {
+        rest = offset;
+    }
+    Some(text.len() - rest.len())
+}
+
+fn match_duration(text: &str) -> Option<usize> {
+    let mut len = count_prefix(text, |ch| ch.is_ascii_digit());
+    if len == 0 {
+        return None;
+    }
+    if let Some(fraction) = text[len..].strip_prefix('.') {
+        let fraction_len = count_prefix(fraction, |ch| ch.is_ascii_digit());
+        if fraction_len > 0 {
+            len += 1 + fraction_len;
+        }
+    }
+
+    ["ns", "µs", "us", "ms", "h", "m", "s"]
+        .into_iter()
+        .find(|unit| text[len..].starts_with(unit))
+        .map(|unit| len + unit.len())
+}
+
+fn match_uuid(text: &str) -> Option<usize> {
+    let mut rest = text;
+    for (idx, group_len) in [8, 4, 4, 4, 12].into_iter().enumerate() {
+        if idx > 0 {
+            rest = rest.strip_prefix('-')?;
+        }
+        if count_prefix(rest, |ch| ch.is_ascii_hexdigit()) != group_len {
+            return None;
+        }
+        rest = &rest[group_len..];
+    }
+    Some(text.len() - rest.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{
+        CurrentDirPath, Durations, HexHashes, MacosTempDir, NormalizeContext, Normalizer, TempDirPath, Timestamps,
+        Uuids,
+    };
+
+    fn normalize(normalizer: impl Normalizer, text: &str) -> String {
+        let context = NormalizeContext {
+            test_dir: Path::new("/tmp/test"),
+            current_dir: Path::new("/tmp/test/a"),
+        };
+        normalizer.normalize(text, &context)
+    }
+
+    #[test]
+    fn normalize_paths() {
+        assert_eq!(
+            normalize(CurrentDirPath, "at ${current_dir_path}/b"),
+            "at /tmp/test/a/b"
+        );
+        assert_eq!(normalize(MacosTempDir, "/private/var/folders/x"), "/var/folders/x");
+        assert_eq!(
+            normalize(TempDirPath::default(), "Created /tmp/test/a/Project.toml"),
+            "Created [temp_dir]/a/Project.toml"
+        );
+    }
+
+    #[test]
+    fn normalize_volatile_values() {
+        assert_eq!(
+            normalize(
+                Timestamps,
+                "at 2024-01-31T12:34:56.789Z, 2024-01-31 12:34 +01:00 and 2024-01-31T12:34:56+0100 not 2024-01-31"
+            ),
+            "at [timestamp], [timestamp] and [timestamp] not 2024-01-31"
+        );
+        assert_eq!(
+            normalize(Durations, "Finished in 1.52s (15ms, 3µs), not 5mb or v1.2s"),
+            "Finished in [duration] ([duration], [duration]), not 5mb or v1.2s"
+        );
+        assert_eq!(
+            normalize(Uuids, "id 67e55044-10b1-426f-9247-bb680e5fe0c8 not 67e55044-10b1-426f"),
+            "id [uuid] not 67e55044-10b1-426f"
+        );
+        assert_eq!(
+            normalize(
+                HexHashes::default(),
+                "commit 3f2a9c1d, sha256:9f86d081884c7d65 not 1234567 or defaced"
+            ),
+            "commit [hash], sha256:[hash] not 1234567 or defaced"
+        );
+    }
+}
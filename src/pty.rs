@@ -0,0 +1,273 @@
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::process::{Child, Command, ExitStatus};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// Window size of a pseudo-terminal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PtySize {
+    pub cols: u16,
+    pub rows: u16,
+}
+
+impl Default for PtySize {
+    fn default() -> Self {
+        Self { cols: 80, rows: 24 }
+    }
+}
+
+impl PtySize {
+    /// Parse the `<cols>x<rows>` size, e.g. `120x40`.
+    pub fn parse(value: &str) -> Option<Self> {
+        let (cols, rows) = value.split_once('x')?;
+        Some(Self {
+            cols: cols.parse().ok().filter(|cols| *cols > 0)?,
+            rows: rows.parse().ok().filter(|rows| *rows > 0)?,
+        })
+    }
+}
+
+impl Display for PtySize {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.cols, self.rows)
+    }
+}
+
+/// Time without output after the process exited to consider its output drained.
+const DRAIN_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Result of waiting for the output of a process.
+#[derive(Debug, PartialEq)]
+pub enum Read {
+    Output(Vec<u8>),
+    /// The process closed the terminal, usually by exiting.
+    Eof,
+    TimedOut,
+}
+
+/// Process running in a pseudo-terminal, its stdin, stdout and stderr are all connected to the terminal. The
+/// terminal does not echo the input.
+pub struct PtyProcess {
+    child: Child,
+    #[cfg(unix)]
+    input: std::fs::File,
+    output: Receiver<Vec<u8>>,
+}
+
+impl PtyProcess {
+    /// Spawn the command in a new pseudo-terminal of the size. The terminal becomes the controlling terminal of the
+    /// process.
+    #[cfg(unix)]
+    pub fn spawn(mut cmd: Command, size: PtySize) -> io::Result<Self> {
+        use std::fs::File;
+        use std::io::Read as _;
+        use std::os::unix::process::CommandExt;
+        use std::process::Stdio;
+        use std::thread;
+
+        use nix::pty::{Winsize, openpty};
+        use nix::sys::termios::{LocalFlags, SetArg, tcgetattr, tcsetattr};
+
+        let winsize = Winsize {
+            ws_row: size.rows,
+            ws_col: size.cols,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        let pty = openpty(&winsize, None).map_err(io::Error::from)?;
+
+        let mut termios = tcgetattr(&pty.slave).map_err(io::Error::from)?;
+        termios.local_flags.remove(LocalFlags::ECHO);
+        tcsetattr(&pty.slave, SetArg::TCSANOW, &termios).map_err(io::Error::from)?;
+
+        cmd.stdin(Stdio::from(pty.slave.try_clone()?))
+            .stdout(Stdio::from(pty.slave.try_clone()?))
+            .stderr(Stdio::from(pty.slave));
+        // SAFETY: only async-signal-safe functions are called between fork and exec
+        unsafe {
+            cmd.pre_exec(|| {
+                if libc::setsid() == -1 || libc::ioctl(0, libc::TIOCSCTTY as _, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let child = cmd.spawn()?;
+        // The slave side must be closed in this process to get EOF when the child exits
+        drop(cmd);
+
+        let input = File::from(pty.master);
+        let mut master = input.try_clone()?;
+        let (sender, output) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buf = [0; 4096];
+            // Reading fails with EIO on Linux when the slave side is closed
+            while let Ok(len @ 1..) = master.read(&mut buf) {
+                if sender.send(buf[..len].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self { child, input, output })
+    }
+
+    #[cfg(not(unix))]
+    pub fn spawn(_cmd: Command, _size: PtySize) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "pseudo-terminals are only supported on Unix",
+        ))
+    }
+
+    /// Type the text into the terminal.
+    pub fn write_input(&mut self, text: &str) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            use std::io::Write;
+
+            self.input.write_all(text.as_bytes())?;
+            self.input.flush()
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = text;
+            Err(io::ErrorKind::Unsupported.into())
+        }
+    }
+
+    /// Wait for the next output of the process until the deadline.
+    pub fn read(&mut self, deadline: Option<Instant>) -> Read {
+        let chunk = match deadline {
+            Some(deadline) => self
+                .output
+                .recv_timeout(deadline.saturating_duration_since(Instant::now())),
+            None => self.output.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        match chunk {
+            Ok(chunk) => Read::Output(chunk),
+            Err(RecvTimeoutError::Disconnected) => Read::Eof,
+            Err(RecvTimeoutError::Timeout) => Read::TimedOut,
+        }
+    }
+
+    /// Read the output until the process closes the terminal, or until it exits and its output is drained, `None`
+    /// if the deadline comes first. A background process started by the command may keep the terminal open after
+    /// the command exits.
+    pub fn read_to_end(&mut self, deadline: Option<Instant>) -> Option<Vec<u8>> {
+        let mut output = Vec::new();
+        let mut has_exited = false;
+        loop {
+            let poll_deadline = Instant::now() + DRAIN_INTERVAL;
+            match self.read(Some(
+                deadline.map_or(poll_deadline, |deadline| deadline.min(poll_deadline)),
+            )) {
+                Read::Output(chunk) => output.extend(chunk),
+                Read::Eof => return Some(output),
+                Read::TimedOut if deadline.is_some_and(|deadline| Instant::now() >= deadline) => return None,
+                // The output is drained when nothing comes for a whole interval after the process exited
+                Read::TimedOut if has_exited => return Some(output),
+                Read::TimedOut => has_exited = matches!(self.child.try_wait(), Ok(Some(_))),
+            }
+        }
+    }
+
+    pub fn wait(&mut self) -> io::Result<ExitStatus> {
+        self.child.wait()
+    }
+
+    pub fn kill(&mut self) -> io::Result<()> {
+        self.child.kill()?;
+        self.child.wait().map(|_| ())
+    }
+}
+
+impl Drop for PtyProcess {
+    /// Kill the process if it is still running, e.g. when a prompt does not match.
+    fn drop(&mut self) {
+        if let Ok(None) = self.child.try_wait() {
+            let _ = self.kill();
+        }
+    }
+}
+
+/// Render the output of a terminal into plain lines: the line endings are converted to `\n` and each line shows the
+/// text written after its last carriage return or erase-line sequence, e.g. the final state of a progress bar.
+pub fn render_terminal_output(output: &str) -> String {
+    output
+        .split('\n')
+        .map(|line| {
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            let start = ["\r", "\x1b[2K", "\x1b[K", "\x1b[0K"]
+                .into_iter()
+                .filter_map(|reset| line.rfind(reset).map(|idx| idx + reset.len()))
+                .max()
+                .unwrap_or(0);
+            &line[start..]
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PtySize, render_terminal_output};
+
+    #[test]
+    fn parse_size() {
+        assert_eq!(PtySize::parse("120x40"), Some(PtySize { cols: 120, rows: 40 }));
+        assert_eq!(PtySize::parse("120"), None);
+        assert_eq!(PtySize::parse("0x40"), None);
+        assert_eq!(PtySize::default().to_string(), "80x24");
+    }
+
+    #[test]
+    fn render_output() {
+        assert_eq!(
+            render_terminal_output("Downloading\r\n[==  ] 50%\r[====] 100%\r\n\x1b[2Kdone\r\n"),
+            "Downloading\n[====] 100%\ndone\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_in_pty() {
+        use std::process::Command;
+
+        use super::PtyProcess;
+
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "test -t 1 && stty size && read line && echo \"got $line\""]);
+
+        let mut process = PtyProcess::spawn(cmd, PtySize { cols: 100, rows: 30 }).unwrap();
+        process.write_input("yes\n").unwrap();
+        let output = process.read_to_end(None).unwrap();
+        assert!(process.wait().unwrap().success());
+        assert_eq!(
+            render_terminal_output(&String::from_utf8_lossy(&output)),
+            "30 100\ngot yes\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stop_reading_after_exit() {
+        use std::process::Command;
+        use std::time::{Duration, Instant};
+
+        use super::PtyProcess;
+
+        // The background process ignoring the hangup keeps the terminal open after the command exits
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "trap '' HUP; sleep 3 & echo done"]);
+
+        let started = Instant::now();
+        let mut process = PtyProcess::spawn(cmd, PtySize::default()).unwrap();
+        let output = process.read_to_end(None).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(process.wait().unwrap().success());
+        assert_eq!(render_terminal_output(&String::from_utf8_lossy(&output)), "done\n");
+    }
+}
@@ -0,0 +1,111 @@
+use std::env;
+use std::fmt::Write;
+use std::path::Path;
+
+use assert_cmd::Command;
+
+use crate::Tester;
+use crate::case::TestSection;
+use crate::lexer::shell_quote;
+
+/// Build a shell script that reproduces the executed test cases of the section in a new temporary directory.
+pub fn replay_script(tester: &Tester, section: &TestSection, executed_cases: usize) -> String {
+    let mut script = String::from("#!/bin/sh\n");
+    let _ = writeln!(
+        script,
+        "# Replay of the section `{}` from `{}`",
+        section.title,
+        tester.md_file_path.display()
+    );
+    script.push_str("cd \"$(mktemp -d)\" || exit 1\necho \"Replaying in $(pwd)\"\n\n");
+
+    for (key, val) in &tester.envs {
+        let _ = writeln!(
+            script,
+            "export {}={}",
+            key.to_string_lossy(),
+            shell_quote(&val.to_string_lossy())
+        );
+    }
+
+    if let Some(alias) = &tester.cargo_bin_alias {
+        let bin_name = tester
+            .cargo_bin_name
+            .clone()
+            .or_else(|| env::var("CARGO_PKG_NAME").ok())
+            .unwrap_or_else(|| alias.clone());
+
+        if let Ok(cmd) = Command::cargo_bin(&bin_name) {
+            let bin_path = Path::new(cmd.get_program());
+            if let Some(bin_dir) = bin_path.parent() {
+                let _ = writeln!(
+                    script,
+                    "export PATH={}:\"$PATH\"",
+                    shell_quote(&bin_dir.to_string_lossy())
+                );
+            }
+            let _ = writeln!(
+                script,
+                "{alias}() {{ {} \"$@\"; }}",
+                shell_quote(&bin_path.to_string_lossy())
+            );
+        }
+    }
+
+    for fixture_dir in tester.fixture_dirs.iter().chain(&section.fixtures) {
+        let fixture_dir = fixture_dir.canonicalize().unwrap_or_else(|_| fixture_dir.clone());
+        let _ = writeln!(script, "cp -R {}/. .", shell_quote(&fixture_dir.to_string_lossy()));
+    }
+
+    for test_case in section.setup.iter().chain(&section.cases).take(executed_cases) {
+        if test_case.skip_reason.is_some() {
+            continue;
+        }
+
+        script.push('\n');
+        if let Some(line) = test_case.output.source_line {
+            let _ = writeln!(script, "# line {line}");
+        }
+
+        if let Some(file) = &test_case.file {
+            let path = shell_quote(&file.path);
+            let _ = writeln!(script, "mkdir -p \"$(dirname {path})\"");
+            let _ = writeln!(script, "cat > {path} <<'MD_CLI_TEST_EOF'");
+            script.push_str(&file.content);
+            if !file.content.ends_with('\n') {
+                script.push('\n');
+            }
+            script.push_str("MD_CLI_TEST_EOF\n");
+        }
+
+        if let Some(path) = &test_case.expected_file {
+            let _ = writeln!(script, "cat {}", shell_quote(path));
+        }
+
+        // Every test case starts in the test directory
+        if !test_case.commands.is_empty() {
+            script.push_str("(\n");
+            for (key, val) in &test_case.options.envs {
+                let _ = writeln!(script, "export {key}={}", shell_quote(val));
+            }
+            if let Some(dir) = &test_case.options.working_dir {
+                let _ = writeln!(script, "cd {} || exit 1", shell_quote(dir));
+            }
+            if !test_case.interaction.is_empty() {
+                let inputs = test_case
+                    .interaction
+                    .iter()
+                    .map(|step| format!("{:?}", step.input))
+                    .collect::<Vec<_>>();
+                let _ = writeln!(script, "# interactive, type: {}", inputs.join(", "));
+            }
+            for command in &test_case.commands {
+                script.push_str(command);
+                script.push('\n');
+            }
+            script.push_str(")\n");
+        }
+    }
+
+    script
+}
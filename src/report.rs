@@ -0,0 +1,161 @@
+use std::any::Any;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Results of the markdown files run by [`Tester::execute`](crate::Tester::execute).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    pub files: Vec<FileReport>,
+}
+
+impl Report {
+    /// Add the files of the other report, e.g. to build a single report of several testers.
+    pub fn merge(&mut self, other: Report) {
+        self.files.extend(other.files);
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.files.iter().all(FileReport::is_success)
+    }
+
+    /// Iterate over the sections of all files.
+    pub fn sections(&self) -> impl Iterator<Item = &SectionReport> {
+        self.files.iter().flat_map(|file| &file.sections)
+    }
+
+    /// Iterate over the commands of all files.
+    pub fn commands(&self) -> impl Iterator<Item = &CommandReport> {
+        self.sections().flat_map(|section| &section.commands)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileReport {
+    pub path: PathBuf,
+    pub sections: Vec<SectionReport>,
+    pub duration: Duration,
+}
+
+impl FileReport {
+    pub fn is_success(&self) -> bool {
+        self.sections.iter().all(|section| section.status != Status::Failed)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Status {
+    #[default]
+    Passed,
+    Failed,
+    /// The test case is skipped by a directive or an unmet condition.
+    Skipped,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SectionReport {
+    pub title: String,
+    /// Failed if a command or the section itself, e.g. its fixtures or teardown, failed. Skipped if all its
+    /// commands are skipped.
+    pub status: Status,
+    pub duration: Duration,
+    /// Commands of the setup, regular and teardown cases in the order they ran.
+    pub commands: Vec<CommandReport>,
+    /// Error or panic message of the failure.
+    pub failure: Option<String>,
+}
+
+impl SectionReport {
+    /// Build the report of the section from its commands and its failure, if any.
+    pub fn new(title: impl Into<String>, commands: Vec<CommandReport>, failure: Option<String>) -> Self {
+        let status = if failure.is_some() || commands.iter().any(|command| command.status == Status::Failed) {
+            Status::Failed
+        } else if !commands.is_empty() && commands.iter().all(|command| command.status == Status::Skipped) {
+            Status::Skipped
+        } else {
+            Status::Passed
+        };
+
+        Self {
+            title: title.into(),
+            status,
+            duration: commands.iter().map(|command| command.duration).sum(),
+            commands,
+            failure,
+        }
+    }
+}
+
+/// Result of a command line of a test case, or of an `expect-file` check.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CommandReport {
+    pub command: String,
+    pub status: Status,
+    pub duration: Duration,
+    /// Normalized expected output, `None` if the command has no checked output.
+    pub expected: Option<String>,
+    /// Normalized actual output, `None` if the command has no checked output.
+    pub actual: Option<String>,
+    /// Exit status of the last pipeline of the command.
+    pub exit_code: Option<i32>,
+    pub source_path: Option<PathBuf>,
+    /// Line of the code block of the test case.
+    pub source_line: Option<usize>,
+    pub skip_reason: Option<String>,
+    /// Error or panic message of the failure.
+    pub failure: Option<String>,
+}
+
+/// Collects the reports of the commands run by the test cases of a section.
+#[derive(Debug, Clone, Default)]
+pub struct Recorder(Arc<Mutex<Vec<CommandReport>>>);
+
+impl Recorder {
+    pub fn record(&self, report: CommandReport) {
+        self.0.lock().unwrap_or_else(|err| err.into_inner()).push(report);
+    }
+
+    /// Take the recorded reports.
+    pub fn take(&self) -> Vec<CommandReport> {
+        std::mem::take(&mut *self.0.lock().unwrap_or_else(|err| err.into_inner()))
+    }
+}
+
+/// Message of a caught panic, e.g. of a failed assertion.
+pub fn panic_message(panic: &(dyn Any + Send)) -> String {
+    if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else if let Some(message) = panic.downcast_ref::<&str>() {
+        message.to_string()
+    } else {
+        "panicked".into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{CommandReport, SectionReport, Status};
+
+    #[test]
+    fn section_status() {
+        let command = |status, millis| CommandReport {
+            status,
+            duration: Duration::from_millis(millis),
+            ..Default::default()
+        };
+
+        let section = SectionReport::new("a", vec![command(Status::Passed, 1), command(Status::Skipped, 2)], None);
+        assert_eq!(
+            (section.status, section.duration),
+            (Status::Passed, Duration::from_millis(3))
+        );
+        let section = SectionReport::new("a", vec![command(Status::Skipped, 0)], None);
+        assert_eq!(section.status, Status::Skipped);
+        let section = SectionReport::new("a", vec![command(Status::Passed, 0), command(Status::Failed, 0)], None);
+        assert_eq!(section.status, Status::Failed);
+        let section = SectionReport::new("a", vec![], Some("teardown failed".into()));
+        assert_eq!(section.status, Status::Failed);
+    }
+}
@@ -0,0 +1,310 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Placeholder of the URL of the stub server in the commands, the envs and the expected output.
+pub const STUB_URL_PLACEHOLDER: &str = "${stub_url}";
+
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Response of the stub server to a request, declared by a `stub` code block:
+///
+/// ```http
+/// GET /api/projects
+///
+/// HTTP/1.1 200 OK
+/// Content-Type: application/json
+///
+/// [{"name": "project A"}]
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpStub {
+    pub method: String,
+    /// Path of the request, the query is compared only if the path of the stub has one.
+    pub path: String,
+    pub status: u16,
+    pub reason: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+    /// Whether the section fails if the stub is never requested.
+    pub required: bool,
+    pub source_path: Option<PathBuf>,
+    pub source_line: Option<usize>,
+}
+
+impl HttpStub {
+    /// Parse the request line, an empty line and the response of the stub block.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (request, rest) = split_line(text);
+        let (method, path) = request
+            .split_once(' ')
+            .filter(|(method, path)| !method.is_empty() && path.starts_with('/'))
+            .ok_or_else(|| format!("invalid request `{request}`, expected `<method> <path>`"))?;
+        let (empty, rest) = split_line(rest);
+        if !empty.is_empty() {
+            return Err("expected an empty line after the request".into());
+        }
+
+        let (status_line, mut rest) = split_line(rest);
+        let status_line = match status_line.split_once(' ') {
+            Some((version, status_line)) if version.starts_with("HTTP/") => status_line,
+            _ => status_line,
+        };
+        let (status, reason) = status_line.split_once(' ').unwrap_or((status_line, ""));
+        let status = status
+            .parse()
+            .ok()
+            .filter(|status| (100..1000).contains(status))
+            .ok_or_else(|| format!("invalid status `{status}`"))?;
+
+        // The headers end with an empty line before the body
+        let mut headers = Vec::new();
+        while !rest.is_empty() {
+            let (line, next) = split_line(rest);
+            rest = next;
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line
+                .split_once(':')
+                .ok_or_else(|| format!("invalid header `{line}`, expected `<name>: <value>`"))?;
+            headers.push((name.trim().into(), value.trim().into()));
+        }
+
+        Ok(Self {
+            method: method.into(),
+            path: path.trim().into(),
+            status,
+            reason: reason.into(),
+            headers,
+            body: rest.into(),
+            required: false,
+            source_path: None,
+            source_line: None,
+        })
+    }
+
+    fn matches(&self, method: &str, target: &str) -> bool {
+        let path = if self.path.contains('?') {
+            target
+        } else {
+            target.split_once('?').map_or(target, |(path, _)| path)
+        };
+        self.method == method && self.path == path
+    }
+
+    fn write_response(&self, stream: &mut impl Write) -> io::Result<()> {
+        write!(stream, "HTTP/1.1 {} {}\r\n", self.status, self.reason)?;
+        for (name, value) in &self.headers {
+            write!(stream, "{name}: {value}\r\n")?;
+        }
+        if !self
+            .headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        {
+            write!(stream, "Content-Length: {}\r\n", self.body.len())?;
+        }
+        write!(stream, "Connection: close\r\n\r\n{}", self.body)?;
+        stream.flush()
+    }
+
+    /// Source location of the stub block, e.g. `README.md:12`.
+    pub fn source_location(&self) -> String {
+        let source_path = self
+            .source_path
+            .as_ref()
+            .map(|path| path.display().to_string())
+            .unwrap_or_default();
+        format!("{source_path}:{}", self.source_line.unwrap_or_default())
+    }
+}
+
+/// Local HTTP server responding with the stubs of a section, stopped when dropped.
+pub struct StubServer {
+    addr: SocketAddr,
+    stubs: Arc<Vec<HttpStub>>,
+    /// Number of the requests served by each stub.
+    hits: Arc<Mutex<Vec<usize>>>,
+    is_stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl StubServer {
+    /// Start the server on a free loopback port.
+    pub fn start(stubs: Vec<HttpStub>) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let addr = listener.local_addr()?;
+        let stubs = Arc::new(stubs);
+        let hits = Arc::new(Mutex::new(vec![0; stubs.len()]));
+        let is_stopped = Arc::<AtomicBool>::default();
+
+        let thread = thread::spawn({
+            let (stubs, hits, is_stopped) = (stubs.clone(), hits.clone(), is_stopped.clone());
+            move || {
+                for stream in listener.incoming() {
+                    if is_stopped.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let result = stream.and_then(|stream| serve(stream, &stubs, &hits));
+                    if let Err(err) = result {
+                        log::error!("Stub server failed to serve a request: {err}");
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            addr,
+            stubs,
+            hits,
+            is_stopped,
+            thread: Some(thread),
+        })
+    }
+
+    /// URL of the server without a trailing slash, e.g. `http://127.0.0.1:36157`.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Required stubs that have not been requested.
+    pub fn unused_required_stubs(&self) -> Vec<&HttpStub> {
+        let hits = self.hits.lock().unwrap_or_else(|err| err.into_inner());
+        self.stubs
+            .iter()
+            .zip(hits.iter())
+            .filter(|(stub, hits)| stub.required && **hits == 0)
+            .map(|(stub, _)| stub)
+            .collect()
+    }
+}
+
+impl Drop for StubServer {
+    fn drop(&mut self) {
+        self.is_stopped.store(true, Ordering::SeqCst);
+        // Wake up the blocked `accept`
+        let _ = TcpStream::connect(self.addr);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Split the first line without its line ending from the rest of the text.
+fn split_line(text: &str) -> (&str, &str) {
+    let (line, rest) = text.split_once('\n').unwrap_or((text, ""));
+    (line.strip_suffix('\r').unwrap_or(line), rest)
+}
+
+fn serve(mut stream: TcpStream, stubs: &[HttpStub], hits: &Mutex<Vec<usize>>) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.trim().eq_ignore_ascii_case("content-length")
+        {
+            content_length = value.trim().parse().unwrap_or(0);
+        }
+    }
+    io::copy(&mut reader.by_ref().take(content_length), &mut io::sink())?;
+
+    match stubs.iter().position(|stub| stub.matches(method, target)) {
+        Some(idx) => {
+            log::debug!("Stub server: {method} {target} -> {}", stubs[idx].status);
+            hits.lock().unwrap_or_else(|err| err.into_inner())[idx] += 1;
+            stubs[idx].write_response(&mut stream)
+        },
+        None => {
+            log::warn!("Stub server: no stub for {method} {target}");
+            let body = format!("No stub for {method} {target}\n");
+            write!(
+                stream,
+                "HTTP/1.1 404 Not Found\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    use super::{HttpStub, StubServer};
+
+    #[test]
+    fn parse_stubs() {
+        let stub = HttpStub::parse(
+            "POST /api/projects\n\nHTTP/1.1 201 Created\nContent-Type: application/json\n\n{\"id\": 1}\n",
+        )
+        .unwrap();
+        assert_eq!(stub.method, "POST");
+        assert_eq!(stub.path, "/api/projects");
+        assert_eq!((stub.status, stub.reason.as_str()), (201, "Created"));
+        assert_eq!(stub.headers, [("Content-Type".into(), "application/json".into())]);
+        assert_eq!(stub.body, "{\"id\": 1}\n");
+
+        let stub = HttpStub::parse("DELETE /api/projects/1\n\n204\n").unwrap();
+        assert_eq!((stub.status, stub.reason.as_str(), stub.body.as_str()), (204, "", ""));
+
+        for invalid in [
+            "",
+            "GET api\n\n200\n",
+            "GET /api\n200\n",
+            "GET /api\n\nHTTP/1.1 OK\n",
+            "GET /api\n\n200\nContent-Type\n",
+        ] {
+            assert!(HttpStub::parse(invalid).is_err(), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn serve_stubs() {
+        let mut stub = HttpStub::parse("GET /api/projects\n\n200 OK\n\n[]\n").unwrap();
+        stub.required = true;
+        let mut unused = HttpStub::parse("GET /api/tasks\n\n200\n").unwrap();
+        unused.required = true;
+        let server = StubServer::start(vec![stub, unused]).unwrap();
+
+        let request = |request: &str| {
+            let mut stream = TcpStream::connect(server.url().trim_start_matches("http://")).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        assert_eq!(
+            request("GET /api/projects?all=1 HTTP/1.1\r\nHost: localhost\r\n\r\n"),
+            "HTTP/1.1 200 OK\r\nContent-Length: 3\r\nConnection: close\r\n\r\n[]\n"
+        );
+        assert!(
+            request("POST /api/projects HTTP/1.1\r\nContent-Length: 2\r\n\r\n{}").starts_with("HTTP/1.1 404 Not Found")
+        );
+        assert_eq!(
+            server
+                .unused_required_stubs()
+                .iter()
+                .map(|stub| stub.path.as_str())
+                .collect::<Vec<_>>(),
+            ["/api/tasks"]
+        );
+    }
+}
@@ -0,0 +1,349 @@
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+use md_cli_test::ansi::AnsiMode;
+use md_cli_test::command_stub::CommandStub;
+use md_cli_test::config::ConfigPrecedence;
+use md_cli_test::error::TestError;
+use md_cli_test::normalize::Timestamps;
+use md_cli_test::report::{Status, panic_message};
+use md_cli_test::{Tester, hermetic};
+use temp_testdir::TempDir;
+
+/// Path of the markdown file in `tests/markdown`.
+fn markdown(name: &str) -> PathBuf {
+    Path::new("tests/markdown").join(name)
+}
+
+/// Write the markdown to `test.md` in a new temporary directory, which is removed when dropped.
+fn write_markdown(markdown: &str) -> (TempDir, PathBuf) {
+    let dir = TempDir::default();
+    let path = dir.join("test.md");
+    fs::write(&path, markdown).unwrap();
+    (dir, path)
+}
+
+#[test]
+fn run_with_fixtures() {
+    Tester::new(markdown("fixtures.md"))
+        .with_fixture_dir("tests/fixtures/base")
+        .run()
+        .unwrap();
+}
+
+#[test]
+#[should_panic(expected = "Command `expect-file=project A/Project.toml`")]
+fn run_with_unexpected_file() {
+    Tester::new(markdown("unexpected_file.md")).run().unwrap();
+}
+
+#[test]
+fn run_with_inline_files() {
+    Tester::new(markdown("inline_files.md")).run().unwrap();
+}
+
+#[test]
+fn keep_failed_dir_with_replay_script() {
+    let result = Tester::new(markdown("replay_script.md"))
+        .with_env("TODO_CONFIG", "./todo.toml")
+        .with_replay_script(true)
+        .run();
+
+    let Err(TestError::KeptDir { source, dir: kept_dir }) = result else {
+        panic!("Unexpected result: {result:?}");
+    };
+    assert!(matches!(*source, TestError::Command(_)), "{source:?}");
+    assert!(kept_dir.join("a").is_dir());
+
+    let script = fs::read_to_string(kept_dir.join("replay.sh")).unwrap();
+    fs::remove_dir_all(&kept_dir).unwrap();
+
+    assert!(script.contains("export TODO_CONFIG=./todo.toml\n"), "{script}");
+    assert!(
+        script.contains("cat > todo.toml <<'MD_CLI_TEST_EOF'\nname = \"it's\"\nMD_CLI_TEST_EOF\n"),
+        "{script}"
+    );
+    assert!(script.contains("(\nmkdir a && cd a\ncat missing.txt\n)\n"), "{script}");
+    assert!(!script.contains("ls ."), "{script}");
+}
+
+#[test]
+fn keep_failed_dir_on_mismatched_output() {
+    let kept_dir = |message: &str| {
+        let (_, path) = message.split_once("Test directory is kept at `").unwrap();
+        PathBuf::from(path.trim_end_matches('`'))
+    };
+
+    let tester = Tester::new(markdown("mismatched_output.md")).with_keep_failed_dir(true);
+    let panic = panic::catch_unwind(AssertUnwindSafe(|| tester.clone().run())).unwrap_err();
+    let message = panic_message(panic.as_ref());
+    assert!(message.starts_with("assertion `left == right` failed"), "{message}");
+    let path = kept_dir(&message);
+    assert!(path.join("a").is_dir(), "{message}");
+    fs::remove_dir_all(path).unwrap();
+
+    let report = tester.execute().unwrap();
+    let failure = report.files[0].sections[0].failure.as_deref().unwrap();
+    let path = kept_dir(failure);
+    assert!(path.join("a").is_dir(), "{failure}");
+    fs::remove_dir_all(path).unwrap();
+}
+
+#[test]
+fn run_setup_and_teardown() {
+    let result = Tester::new(markdown("setup_and_teardown.md"))
+        .with_keep_failed_dir(true)
+        .run();
+
+    let Err(TestError::KeptDir { dir: kept_dir, .. }) = result else {
+        panic!("Unexpected result: {result:?}");
+    };
+    let teardown_done = kept_dir.join("section-teardown").is_dir();
+    let file_teardown_done = !kept_dir.join("file-setup").exists();
+    fs::remove_dir_all(&kept_dir).unwrap();
+
+    assert!(teardown_done);
+    assert!(file_teardown_done);
+}
+
+#[test]
+fn run_with_directives() {
+    Tester::new(markdown("directives.md")).run().unwrap();
+}
+
+#[test]
+fn run_with_invalid_directive() {
+    let (_dir, path) = write_markdown("# Title\n\n<!-- md-cli-test: match=fuzzy -->\n");
+    let result = Tester::new(path).run();
+    let Err(TestError::Directive {
+        reason, source_line, ..
+    }) = result
+    else {
+        panic!("Unexpected result: {result:?}");
+    };
+    assert_eq!(reason, "unknown match mode `fuzzy`");
+    assert_eq!(source_line, 3);
+
+    let (_dir, path) = write_markdown("# Title\n\n<!--\nmd-cli-test:\n  timeout=soon\n-->\n");
+    let result = Tester::new(path).run();
+    assert!(
+        matches!(result, Err(TestError::Directive { source_line: 3, .. })),
+        "{result:?}"
+    );
+
+    let (_dir, path) = write_markdown("# Title\n\nText <!-- md-cli-test: skip -->\n");
+    let result = Tester::new(path).run();
+    assert!(
+        matches!(result, Err(TestError::Directive { source_line: 3, .. })),
+        "{result:?}"
+    );
+}
+
+#[test]
+fn run_with_job_builtins() {
+    let (_dir, path) =
+        write_markdown("# Jobs\n\n```sh\n$ wait\n$ kill\n$ kill %1 || mkdir failed\n$ ls .\nfailed\n```\n");
+    Tester::new(path).run().unwrap();
+
+    let (_dir, path) = write_markdown("# Jobs\n\n```sh\n$ echo serving &\n```\n");
+    let result = Tester::new(path).run();
+    assert!(
+        matches!(&result, Err(TestError::Command(message)) if message.contains("cannot run in the background")),
+        "{result:?}"
+    );
+}
+
+#[test]
+fn run_with_stubs() {
+    let stub = "```http,stub\nGET /api/projects\n\n200 OK\n\n[]\n```\n";
+
+    let (_dir, path) = write_markdown(&format!(
+        "{stub}\n# Stubs\n\n<!-- md-cli-test: match=contains -->\n```sh\n$ echo url ${{stub_url}}/api\nurl ${{stub_url}}/api\n```\n"
+    ));
+    Tester::new(path).run().unwrap();
+
+    let (_dir, path) = write_markdown(&format!(
+        "# Stubs\n\n{}\n```sh\n$ mkdir src\n```\n",
+        stub.replace("stub", "stub,required")
+    ));
+    let result = Tester::new(path).run();
+    assert!(
+        matches!(&result, Err(TestError::Failed(message)) if message.contains("`GET /api/projects` in source")),
+        "{result:?}"
+    );
+
+    let (_dir, path) = write_markdown("# Stubs\n\n```http,stub\nGET /api\n200\n```\n");
+    let result = Tester::new(path).run();
+    assert!(
+        matches!(result, Err(TestError::Directive { source_line: 3, .. })),
+        "{result:?}"
+    );
+}
+
+#[test]
+fn run_with_stub_commands() {
+    let (_dir, path) = write_markdown(
+        "```text,command=editor,exit=1\nsaved\n```\n\n# Stub commands\n\n```sh\n$ stub-log editor\n```\n",
+    );
+    Tester::new(path)
+        .with_stub_command(CommandStub::script("git", "exit 0"))
+        .run()
+        .unwrap();
+
+    let (_dir, path) = write_markdown("# Stub commands\n\n```sh\n$ stub-log\n```\n");
+    let result = Tester::new(path).run();
+    assert!(matches!(result, Err(TestError::Command(_))), "{result:?}");
+}
+
+#[test]
+fn run_hermetic() {
+    let (_dir, path) = write_markdown("# Hermetic\n\n```sh\n$ mkdir src && ls .\nsrc\n```\n");
+    let tester = Tester::new(path).hermetic().with_allowed_env("RUST_LOG");
+    assert!(tester.allowed_envs.contains(&"PATH".to_string()));
+    tester.run().unwrap();
+
+    // The home directory is next to the test directory and kept with it
+    let (_dir, path) = write_markdown("# Hermetic\n\n```sh\n$ cat missing.txt\n```\n");
+    let result = Tester::new(path).hermetic().with_keep_failed_dir(true).run();
+    let Err(TestError::KeptDir { dir: kept_dir, .. }) = result else {
+        panic!("Unexpected result: {result:?}");
+    };
+    let hermetic_dir = hermetic::hermetic_dir(&kept_dir);
+    assert!(hermetic_dir.join("home").is_dir());
+    fs::remove_dir_all(kept_dir).unwrap();
+    fs::remove_dir_all(hermetic_dir).unwrap();
+}
+
+#[test]
+fn execute_with_report() {
+    let report = Tester::new(markdown("report.md")).execute().unwrap();
+    assert!(!report.is_success());
+    assert_eq!(report.files[0].path, markdown("report.md"));
+    let sections = report
+        .sections()
+        .map(|section| (section.title.as_str(), section.status, section.commands.len()))
+        .collect::<Vec<_>>();
+    assert_eq!(sections, [
+        ("Passed", Status::Passed, 2),
+        ("Failed", Status::Failed, 2),
+        ("Error", Status::Failed, 1),
+        ("Skipped", Status::Skipped, 1),
+    ]);
+
+    let failed = &report.files[0].sections[1].commands[1];
+    assert_eq!((failed.command.as_str(), failed.status), ("ls .", Status::Failed));
+    assert_eq!(
+        (failed.expected.as_deref(), failed.actual.as_deref()),
+        (Some("b\n"), Some("a\n"))
+    );
+    assert_eq!((failed.exit_code, failed.source_line), (Some(0), Some(11)));
+    assert!(failed.failure.as_ref().unwrap().contains("Command `ls .` in source"));
+
+    let error = &report.files[0].sections[2];
+    assert!(error.failure.as_ref().unwrap().starts_with("Command IO error"));
+    let skipped = &report.files[0].sections[3].commands[0];
+    assert_eq!(skipped.skip_reason.as_deref(), Some("skipped by directive"));
+}
+
+#[test]
+fn run_with_junit_report() {
+    let dir = TempDir::default();
+    let result = Tester::new(markdown("junit_report.md"))
+        .with_junit_report(dir.join("junit"))
+        .run();
+    assert!(matches!(result, Err(TestError::Command(_))), "{result:?}");
+
+    let xml = fs::read_to_string(dir.join("junit/tests-markdown-junit_report.md.xml")).unwrap();
+    assert!(
+        xml.contains(r#"<testsuite name="tests/markdown/junit_report.md""#)
+            && xml.contains(r#"tests="2" failures="1""#),
+        "{xml}"
+    );
+    assert!(
+        xml.contains(r#"<testcase name="Missing file""#) && xml.contains(r#" line="3""#),
+        "{xml}"
+    );
+    assert!(xml.contains(r#"<testcase name="Next section""#), "{xml}");
+}
+
+#[test]
+fn write_junit_reports_of_files_with_same_name() {
+    let dir = TempDir::default();
+    for name in ["junit/a/README.md", "junit/b/README.md"] {
+        Tester::new(markdown(name))
+            .with_junit_report(dir.join("junit"))
+            .execute()
+            .unwrap();
+    }
+
+    let report = |name: &str| fs::read_to_string(dir.join("junit").join(name)).unwrap();
+    let xml = report("tests-markdown-junit-a-README.md.xml");
+    assert!(xml.contains("Section a") && !xml.contains("<failure"), "{xml}");
+    let xml = report("tests-markdown-junit-b-README.md.xml");
+    assert!(xml.contains("Section b") && xml.contains("<failure"), "{xml}");
+}
+
+#[test]
+fn run_with_front_matter() {
+    Tester::new(markdown("front_matter.md")).run().unwrap();
+
+    let result = Tester::new(markdown("front_matter.md")).with_language("sh").run();
+    assert!(matches!(result, Err(TestError::Command(_))), "{result:?}");
+
+    Tester::new(markdown("front_matter.md"))
+        .with_language("sh")
+        .with_config_precedence(ConfigPrecedence::File)
+        .run()
+        .unwrap();
+
+    let (_dir, path) = write_markdown("+++\nlanguages = \"console\"\n+++\n");
+    let result = Tester::new(path).run();
+    assert!(matches!(result, Err(TestError::Config { .. })), "{result:?}");
+}
+
+#[test]
+fn run_with_project_config() {
+    Tester::new(markdown("project_config.md"))
+        .with_project_dir("tests/projects/config")
+        .run()
+        .unwrap();
+
+    // The front matter overrides the project config
+    let (_dir, path) = write_markdown("---\nlanguages: [sh]\n---\n# Title\n```console\n$ cat missing.txt\n```\n");
+    Tester::new(path)
+        .with_project_dir("tests/projects/config")
+        .run()
+        .unwrap();
+}
+
+#[test]
+fn run_with_conditions() {
+    Tester::new(markdown("conditions.md")).run().unwrap();
+
+    let result = Tester::new(markdown("conditions.md")).with_feature("sqlite").run();
+    assert!(matches!(result, Err(TestError::Command(_))), "{result:?}");
+}
+
+#[test]
+fn run_with_normalizers() {
+    Tester::new(markdown("normalizers.md"))
+        .with_project_dir("tests/projects/normalize")
+        .with_normalizer(Timestamps)
+        .run()
+        .unwrap();
+
+    let dir = TempDir::default();
+    fs::write(dir.join("md-cli-test.toml"), "normalize = [\"unknown\"]\n").unwrap();
+    let result = Tester::new(markdown("normalizers.md")).with_project_dir(&*dir).run();
+    assert!(matches!(result, Err(TestError::Config { .. })), "{result:?}");
+}
+
+#[test]
+fn run_with_ansi_modes() {
+    let (_dir, path) = write_markdown(
+        "# ANSI\n\n```txt file=out.txt\n\x1b[1;31merror\x1b[0m: failed\n```\n\n```sh\n$ cat out.txt\nerror: failed\n```\n\n\
+         <!-- md-cli-test: ansi=markup -->\n\n```sh\n$ cat out.txt\n[bold red]error[/]: failed\n```\n",
+    );
+    Tester::new(path).with_ansi_mode(AnsiMode::Strip).run().unwrap();
+}